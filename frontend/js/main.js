@@ -107,7 +107,11 @@ class WishListItem extends React.Component
             e("div", {className: "ItemInfo"},
               e("span", {className: "ItemName"},
                 e("a", {"href": this.props.url, className: "ItemLink"},
-                  this.props.name)),
+                  this.props.name),
+                this.props.failure_count > 0 ?
+                e("span", {className: "ItemError",
+                           title: `Failed ${this.props.failure_count} times: ${this.props.last_error}`},
+                  "⚠") : null),
               e("span", {className: "ItemPrice"},
                 e("a", {"href": "#", className: "PriceLink", onClick: this.onPriceLink },
                   this.props.price)));
@@ -143,7 +147,9 @@ class WishList extends React.Component
     {
         let items = this.state.items.map((s) =>
             e(WishListItem, {url: s.url, name: s.name, price: s.price_str,
-                             store: s.store, in_store_id: s.id}));
+                             store: s.store, in_store_id: s.id,
+                             failure_count: s.failure_count,
+                             last_error: s.last_error}));
         return e("ul", {id: "Wishlist"}, items);
     }
 }
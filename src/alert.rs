@@ -0,0 +1,375 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::store::{Availability, ItemInfo};
+use crate::utils::Money;
+
+/// When to alert on an item. If none of the price conditions is
+/// set, any price drop triggers an alert.
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Settings
+{
+    /// Price * 100. Alert when the price drops to this or lower.
+    pub alert_price: Option<u64>,
+    /// Alert when the price drops by at least this percent at once.
+    pub alert_drop_percent: Option<u32>,
+    /// Alert when the price is lower than ever before.
+    #[serde(default)]
+    pub alert_all_time_low: bool,
+    /// Alert when the item can be ordered again after being out of
+    /// stock or delisted.
+    #[serde(default)]
+    pub alert_back_in_stock: bool,
+}
+
+impl Settings
+{
+    pub fn fromItem(item: &ItemInfo) -> Self
+    {
+        Self {
+            alert_price: item.alert_price,
+            alert_drop_percent: item.alert_drop_percent,
+            alert_all_time_low: item.alert_all_time_low,
+            alert_back_in_stock: item.alert_back_in_stock,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error>
+    {
+        if self.alert_price == Some(0)
+        {
+            return Err(error!(InvalidInput, "Alert price must be positive"));
+        }
+        if let Some(p) = self.alert_drop_percent
+        {
+            if p == 0 || p >= 100
+            {
+                return Err(error!(InvalidInput,
+                                  "Alert drop percent must be between 1 and 99"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn applyTo(&self, item: &mut ItemInfo)
+    {
+        item.alert_price = self.alert_price;
+        item.alert_drop_percent = self.alert_drop_percent;
+        item.alert_all_time_low = self.alert_all_time_low;
+        item.alert_back_in_stock = self.alert_back_in_stock;
+    }
+
+    /// Whether none of the price conditions is set.
+    fn isEmpty(&self) -> bool
+    {
+        self.alert_price.is_none() && self.alert_drop_percent.is_none() &&
+            !self.alert_all_time_low
+    }
+}
+
+/// Parse an alert price as given by the user, e.g. “15.00” or “¥1,980”,
+/// in `currency`, the currency of the item. Return price * 100.
+pub fn parseAlertPrice(p: &str, currency: &str) -> Result<u64, Error>
+{
+    // The price parser ignores signs.
+    if p.trim_start().starts_with('-')
+    {
+        return Err(error!(InvalidInput, "Alert price must be positive: {}", p));
+    }
+    let price = Money::parse(p, currency).map_err(
+        |e| error_from!(InvalidInput, e, "Invalid alert price: {}", p))?;
+    if price.amount <= 0
+    {
+        return Err(error!(InvalidInput, "Alert price must be positive: {}", p));
+    }
+    Ok(price.amount as u64)
+}
+
+/// Why an alert is sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason
+{
+    /// The price dropped, and the item has no alert settings.
+    Drop,
+    /// The price is at or below the alert price.
+    BelowThreshold(u64),
+    /// The price dropped by this percent.
+    PercentDrop(u32),
+    /// The price is lower than ever before.
+    AllTimeLow,
+    /// The item can be ordered again.
+    BackInStock,
+}
+
+/// Decide whether to alert when the price of `item` changes to
+/// `new_price`. `lowest` is the lowest price in the history of the
+/// item, not counting `new_price`. Only price drops are considered.
+pub fn alertReason(item: &ItemInfo, new_price: i64, lowest: Option<i64>) ->
+    Option<Reason>
+{
+    if new_price >= item.price
+    {
+        return None;
+    }
+    let settings = Settings::fromItem(item);
+    if settings.isEmpty()
+    {
+        return Some(Reason::Drop);
+    }
+    if let Some(threshold) = settings.alert_price
+    {
+        if new_price <= threshold as i64
+        {
+            return Some(Reason::BelowThreshold(threshold));
+        }
+    }
+    if let Some(percent) = settings.alert_drop_percent
+    {
+        let dropped = (item.price - new_price) * 100 / item.price;
+        if dropped >= percent as i64
+        {
+            return Some(Reason::PercentDrop(dropped as u32));
+        }
+    }
+    if settings.alert_all_time_low && lowest.is_none_or(|l| new_price < l)
+    {
+        return Some(Reason::AllTimeLow);
+    }
+    None
+}
+
+/// Decide whether to alert when the availability of `item` changes
+/// to `new_availability`, regardless of the price.
+pub fn stockReason(item: &ItemInfo, new_availability: Availability) ->
+    Option<Reason>
+{
+    if item.alert_back_in_stock && item.availability.cannotOrder() &&
+        new_availability.canOrder()
+    {
+        Some(Reason::BackInStock)
+    }
+    else
+    {
+        None
+    }
+}
+
+/// The last alert sent for an item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastAlert
+{
+    /// Price * 100
+    pub price: i64,
+    pub time: DateTime<Utc>,
+}
+
+/// The past of an item that matters for alerts.
+#[derive(Debug, Clone, Default)]
+pub struct History
+{
+    /// The lowest price so far.
+    pub lowest: Option<i64>,
+    pub last_alert: Option<LastAlert>,
+}
+
+/// Decide whether to alert when `item` changes to `new`, by
+/// `stockReason()` and then `alertReason()`. Price changes of an item
+/// that cannot be ordered are ignored. Within `cooldown` after an
+/// alert, only alert again if the price is lower than the alerted
+/// price. This avoids repeated alerts for the same deal when the
+/// price or the stock bounces up and down.
+pub fn shouldAlert(item: &ItemInfo, new: &ItemInfo, history: &History,
+                   cooldown: Duration, now: DateTime<Utc>) -> Option<Reason>
+{
+    let new_price = new.price;
+    let reason = match stockReason(item, new.availability)
+    {
+        Some(reason) => reason,
+        None if new.availability.cannotOrder() => return None,
+        None => alertReason(item, new_price, history.lowest)?,
+    };
+    if let Some(last) = &history.last_alert
+    {
+        let cooldown_end = last.time +
+            chrono::Duration::seconds(cooldown.as_secs() as i64);
+        if now < cooldown_end && new_price >= last.price
+        {
+            return None;
+        }
+    }
+    Some(reason)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn item(price: i64, settings: Settings) -> ItemInfo
+    {
+        let mut item = ItemInfo::new("ps4-us", "aaa");
+        item.price = price;
+        settings.applyTo(&mut item);
+        item
+    }
+
+    /// `item` with a new price and availability.
+    fn changed(item: &ItemInfo, price: i64, availability: Availability) ->
+        ItemInfo
+    {
+        let mut new = item.clone();
+        new.price = price;
+        new.availability = availability;
+        new
+    }
+
+    #[test]
+    fn any_drop_without_settings()
+    {
+        let i = item(2000, Settings::default());
+        assert_eq!(alertReason(&i, 1999, Some(1000)), Some(Reason::Drop));
+        assert_eq!(alertReason(&i, 2000, None), None);
+        assert_eq!(alertReason(&i, 2500, None), None);
+    }
+
+    #[test]
+    fn threshold()
+    {
+        let i = item(2000, Settings { alert_price: Some(1500),
+                                      ..Settings::default() });
+        assert_eq!(alertReason(&i, 1600, None), None);
+        assert_eq!(alertReason(&i, 1500, None),
+                   Some(Reason::BelowThreshold(1500)));
+        assert_eq!(alertReason(&i, 1000, None),
+                   Some(Reason::BelowThreshold(1500)));
+    }
+
+    #[test]
+    fn percent_drop()
+    {
+        let i = item(2000, Settings { alert_drop_percent: Some(25),
+                                      ..Settings::default() });
+        assert_eq!(alertReason(&i, 1600, None), None);
+        assert_eq!(alertReason(&i, 1500, None), Some(Reason::PercentDrop(25)));
+        assert_eq!(alertReason(&i, 1000, None), Some(Reason::PercentDrop(50)));
+    }
+
+    #[test]
+    fn all_time_low()
+    {
+        let i = item(2000, Settings { alert_all_time_low: true,
+                                      ..Settings::default() });
+        assert_eq!(alertReason(&i, 1500, Some(1500)), None);
+        assert_eq!(alertReason(&i, 1499, Some(1500)), Some(Reason::AllTimeLow));
+        assert_eq!(alertReason(&i, 1900, None), Some(Reason::AllTimeLow));
+    }
+
+    #[test]
+    fn first_matching_reason()
+    {
+        let i = item(2000, Settings { alert_price: Some(1000),
+                                      alert_drop_percent: Some(10),
+                                      alert_all_time_low: true,
+                                      alert_back_in_stock: false });
+        assert_eq!(alertReason(&i, 900, Some(500)),
+                   Some(Reason::BelowThreshold(1000)));
+        assert_eq!(alertReason(&i, 1500, Some(500)), Some(Reason::PercentDrop(25)));
+        assert_eq!(alertReason(&i, 1950, Some(500)), None);
+    }
+
+    #[test]
+    fn cooldown()
+    {
+        let i = item(2000, Settings::default());
+        let now = Utc::now();
+        let day = Duration::from_secs(86400);
+        let at = |price| changed(&i, price, Availability::InStock);
+        let history = |price, hours_ago| History {
+            lowest: None,
+            last_alert: Some(LastAlert {
+                price,
+                time: now - chrono::Duration::hours(hours_ago),
+            }),
+        };
+        assert_eq!(shouldAlert(&i, &at(1500), &History::default(), day, now),
+                   Some(Reason::Drop));
+        // Same deal again
+        assert_eq!(shouldAlert(&i, &at(1500), &history(1500, 1), day, now), None);
+        assert_eq!(shouldAlert(&i, &at(1800), &history(1500, 1), day, now), None);
+        // Better deal
+        assert_eq!(shouldAlert(&i, &at(1400), &history(1500, 1), day, now),
+                   Some(Reason::Drop));
+        // Cooldown passed
+        assert_eq!(shouldAlert(&i, &at(1500), &history(1500, 25), day, now),
+                   Some(Reason::Drop));
+        // No alert anyway
+        assert_eq!(shouldAlert(&i, &at(2100), &History::default(), day, now), None);
+    }
+
+    #[test]
+    fn back_in_stock()
+    {
+        let mut i = item(2000, Settings { alert_back_in_stock: true,
+                                          ..Settings::default() });
+        i.availability = Availability::OutOfStock;
+        let now = Utc::now();
+        let day = Duration::from_secs(86400);
+        let none = History::default();
+        assert_eq!(shouldAlert(&i, &changed(&i, 2000, Availability::InStock),
+                               &none, day, now),
+                   Some(Reason::BackInStock));
+        assert_eq!(shouldAlert(&i, &changed(&i, 2500, Availability::Preorder),
+                               &none, day, now),
+                   Some(Reason::BackInStock));
+        assert_eq!(shouldAlert(&i, &changed(&i, 2000, Availability::Unknown),
+                               &none, day, now), None);
+        // A price drop while still out of stock
+        assert_eq!(shouldAlert(&i, &changed(&i, 1000, Availability::Delisted),
+                               &none, day, now), None);
+
+        // Not asked for, so only the price matters.
+        i.alert_back_in_stock = false;
+        assert_eq!(shouldAlert(&i, &changed(&i, 2000, Availability::InStock),
+                               &none, day, now), None);
+        assert_eq!(shouldAlert(&i, &changed(&i, 1500, Availability::InStock),
+                               &none, day, now),
+                   Some(Reason::Drop));
+
+        // Was not out of stock
+        let i = item(2000, Settings { alert_back_in_stock: true,
+                                      ..Settings::default() });
+        assert_eq!(shouldAlert(&i, &changed(&i, 2000, Availability::InStock),
+                               &none, day, now), None);
+    }
+
+    #[test]
+    fn parse_alert_price() -> Result<(), Error>
+    {
+        assert_eq!(parseAlertPrice("15", "USD")?, 1500);
+        assert_eq!(parseAlertPrice("$14.99", "USD")?, 1499);
+        assert_eq!(parseAlertPrice("1980", "JPY")?, 198000);
+        assert_eq!(parseAlertPrice("¥1,980", "JPY")?, 198000);
+        assert_eq!(parseAlertPrice("12,50 €", "EUR")?, 1250);
+        for p in ["-5", " -5.00", "0", "0.00", "abc"]
+        {
+            assert_eq!(parseAlertPrice(p, "USD").unwrap_err().kind(),
+                       "invalid_input", "{}", p);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validate()
+    {
+        assert!(Settings::default().validate().is_ok());
+        assert!(Settings { alert_price: Some(0), ..Settings::default() }
+                .validate().is_err());
+        assert!(Settings { alert_drop_percent: Some(100), ..Settings::default() }
+                .validate().is_err());
+        assert!(Settings { alert_drop_percent: Some(30), ..Settings::default() }
+                .validate().is_ok());
+    }
+}
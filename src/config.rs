@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io::prelude::*;
 
@@ -16,9 +17,284 @@ pub struct ConfigParams
     pub port: u16,
     pub db_file: String,
     pub update_interval_sec: u64,
+    /// Whether `wishlist serve` also updates the prices, so that
+    /// running `wishlist update` periodically is not needed.
+    #[serde(default)]
+    pub update_in_server: bool,
     /// Path to the Telegram notifier. If this is set, a Telegram
     /// message will be sent when price drops.
     pub telegram_notifier: Option<String>,
+    /// Where to send notifications when price drops. See
+    /// `NotifierParams`.
+    #[serde(default)]
+    pub notifier: Vec<NotifierParams>,
+    /// After an alert for an item, do not alert again for this long,
+    /// unless the price drops below the alerted price.
+    #[serde(default = "defaultAlertCooldown")]
+    pub alert_cooldown_sec: u64,
+    /// Stores defined by scraping rules instead of code. See
+    /// `CustomStoreParams`.
+    #[serde(default)]
+    pub custom_store: Vec<CustomStoreParams>,
+    /// Maximum number of store queries running at the same time,
+    /// across all stores.
+    #[serde(default = "defaultMaxConcurrentQueries")]
+    pub max_concurrent_queries: usize,
+    /// Limits on how hard each store is queried, keyed by either a
+    /// store name (e.g. “amazon-us”) or a store family (e.g.
+    /// “amazon”), in which case all regions of the family share the
+    /// limit. Stores not listed here use `StoreLimitParams::default()`.
+    #[serde(default = "defaultStoreLimits")]
+    pub store_limits: HashMap<String, StoreLimitParams>,
+    /// How to query the stores. See `HttpParams`.
+    #[serde(default = "HttpParams::default")]
+    pub http: HttpParams,
+    /// ISO 4217 code of the currency to convert prices to in the
+    /// wishlist summary.
+    #[serde(default = "defaultHomeCurrency")]
+    pub home_currency: String,
+    /// Where to get exchange rates. Without this, only prices already
+    /// in `home_currency` can be summed.
+    pub exchange_rates: Option<ExchangeRateParams>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoreLimitParams
+{
+    /// Maximum number of queries to the store at the same time.
+    #[serde(default = "defaultMaxConcurrentPerStore")]
+    pub max_concurrent: usize,
+    /// Maximum number of queries to start per minute. No limit if not
+    /// set.
+    pub requests_per_minute: Option<u32>,
+    /// Time to wait after a query finishes before starting the next
+    /// one.
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+impl StoreLimitParams
+{
+    pub fn default() -> Self
+    {
+        Self {
+            max_concurrent: defaultMaxConcurrentPerStore(),
+            requests_per_minute: None,
+            delay_ms: 0,
+        }
+    }
+}
+
+/// The HTTP client used to query the stores.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HttpParams
+{
+    /// Give up on a request after this long, including reading the
+    /// response.
+    #[serde(default = "defaultTimeout")]
+    pub timeout_sec: u64,
+    #[serde(default = "defaultConnectTimeout")]
+    pub connect_timeout_sec: u64,
+    /// Send all requests through this proxy, e.g.
+    /// “http://proxy.lan:3128” or “socks5://127.0.0.1:1080”.
+    pub proxy: Option<String>,
+    /// User agents to take turns using. Default to a few common
+    /// browsers. No user agent is sent if empty.
+    #[serde(default = "defaultUserAgents")]
+    pub user_agents: Vec<String>,
+    /// Settings for specific stores, keyed by either a store name or
+    /// a store family, like `store_limits`.
+    #[serde(default)]
+    pub stores: HashMap<String, StoreHttpParams>,
+}
+
+impl HttpParams
+{
+    pub fn default() -> Self
+    {
+        Self {
+            timeout_sec: defaultTimeout(),
+            connect_timeout_sec: defaultConnectTimeout(),
+            proxy: None,
+            user_agents: defaultUserAgents(),
+            stores: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct StoreHttpParams
+{
+    /// Extra HTTP headers to send, e.g. “Accept-Language”.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Cookies to send, e.g. a session ID.
+    #[serde(default)]
+    pub cookies: HashMap<String, String>,
+    /// Send the requests to this URL instead of the store, with the
+    /// same path and query, e.g. “http://127.0.0.1:8080”. This is
+    /// for testing against a mock server.
+    pub base_url: Option<String>,
+}
+
+fn defaultMaxConcurrentQueries() -> usize { 8 }
+fn defaultAlertCooldown() -> u64 { 7 * 24 * 3600 }
+fn defaultMaxConcurrentPerStore() -> usize { 2 }
+fn defaultTimeout() -> u64 { 30 }
+fn defaultConnectTimeout() -> u64 { 10 }
+
+fn defaultUserAgents() -> Vec<String>
+{
+    [
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36",
+        "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.6 Safari/605.1.15",
+        "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0",
+    ].iter().map(|s| s.to_string()).collect()
+}
+
+fn defaultHomeCurrency() -> String { String::from("USD") }
+fn defaultRateCacheSec() -> u64 { 24 * 3600 }
+
+/// Amazon is quick to block scrapers.
+fn defaultStoreLimits() -> HashMap<String, StoreLimitParams>
+{
+    let mut limits = HashMap::new();
+    limits.insert(String::from("amazon"), StoreLimitParams {
+        max_concurrent: 1,
+        requests_per_minute: Some(10),
+        delay_ms: 2000,
+    });
+    limits
+}
+
+/// A notification channel. The “type” field in the config selects
+/// the variant.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierParams
+{
+    /// POST the notification as JSON to `url`.
+    Webhook
+    {
+        url: String,
+        /// Extra HTTP headers, e.g. for authentication.
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    /// Publish to an ntfy topic. `url` is the URL of the topic.
+    Ntfy
+    {
+        url: String,
+        /// Access token, if the topic is protected.
+        token: Option<String>,
+        /// 1 (min) to 5 (max).
+        priority: Option<u8>,
+    },
+    /// Push to a Gotify server. `url` is the root of the server.
+    Gotify
+    {
+        url: String,
+        /// Application token.
+        token: String,
+        priority: Option<u8>,
+    },
+    /// Send an email.
+    Smtp(SmtpParams),
+    /// Show a desktop notification with notify-send.
+    NotifySend
+    {
+        /// “low”, “normal”, or “critical”.
+        urgency: Option<String>,
+    },
+    /// Run a program, and write the notification in Markdown to its
+    /// stdin.
+    Command
+    {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity
+{
+    /// Plain text. Only use this with a local server.
+    None,
+    /// Upgrade to TLS with STARTTLS. Port 587 by default.
+    StartTls,
+    /// TLS from the start. Port 465 by default.
+    Tls,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SmtpParams
+{
+    pub host: String,
+    /// Default to the standard port of `security`.
+    pub port: Option<u16>,
+    #[serde(default = "defaultSmtpSecurity")]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn defaultSmtpSecurity() -> SmtpSecurity { SmtpSecurity::StartTls }
+
+/// A source of exchange rates. The “type” field in the config selects
+/// the variant. Either way, the rates are given as how much of each
+/// currency one unit of the base currency buys, i.e.
+///
+///     base = "USD"
+///     [rates]
+///     EUR = 0.92
+///     HKD = 7.8
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExchangeRateParams
+{
+    /// Read the rates from a TOML file.
+    File
+    {
+        path: String,
+    },
+    /// Fetch the rates as JSON from a URL, e.g.
+    /// “https://open.er-api.com/v6/latest/USD”.
+    Http
+    {
+        url: String,
+        /// Keep using the fetched rates for this long.
+        #[serde(default = "defaultRateCacheSec")]
+        cache_sec: u64,
+    },
+}
+
+/// A store that is queried by fetching a URL and extracting the name
+/// and price from the response, either from HTML with CSS selectors,
+/// or from JSON with JSON pointers (RFC 6901).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CustomStoreParams
+{
+    /// Store name to use in `wishlist add`. Must not clash with other
+    /// stores.
+    pub name: String,
+    /// URL to fetch, with “{id}” replaced by the item ID.
+    pub url_template: String,
+    /// URL of the product page shown to the user, with “{id}”
+    /// replaced by the item ID. Defaults to `url_template`.
+    pub page_url_template: Option<String>,
+    pub name_selector: Option<String>,
+    pub price_selector: Option<String>,
+    pub name_pointer: Option<String>,
+    pub price_pointer: Option<String>,
+    /// ISO 4217 currency code of the prices, e.g. “USD”.
+    pub currency: String,
+    /// Time between price updates. Use the global update interval if
+    /// not set.
+    pub update_interval_sec: Option<u64>,
 }
 
 impl ConfigParams
@@ -30,20 +306,31 @@ impl ConfigParams
             port: 8000,
             db_file: String::from("wishlist.db"),
             update_interval_sec: 3600,
+            update_in_server: false,
             telegram_notifier: None,
+            notifier: Vec::new(),
+            alert_cooldown_sec: defaultAlertCooldown(),
+            custom_store: Vec::new(),
+            max_concurrent_queries: defaultMaxConcurrentQueries(),
+            store_limits: defaultStoreLimits(),
+            http: HttpParams::default(),
+            home_currency: defaultHomeCurrency(),
+            exchange_rates: None,
         }
     }
 
     pub fn fromFile(filename: &std::path::Path) -> Result<Self, Error>
     {
         let mut file = fs::File::open(filename).map_err(
-            |_| rterr!("Failed to open file {}", filename.to_string_lossy()))?;
+            |e| error_from!(Config, e, "Failed to open file {}",
+                            filename.to_string_lossy()))?;
         let mut contents = String::new();
         file.read_to_string(&mut contents).map_err(
-            |_| rterr!("Failed to read file {}", filename.to_string_lossy()))?;
+            |e| error_from!(Config, e, "Failed to read file {}",
+                            filename.to_string_lossy()))?;
 
         toml::from_str(&contents).map_err(
-            |e| rterr!("Failed to parse file {}: {}",
-                       filename.to_string_lossy(), e))
+            |e| error_from!(Config, e, "Failed to parse file {}",
+                            filename.to_string_lossy()))
     }
 }
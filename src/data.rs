@@ -3,13 +3,77 @@ use std::time;
 use rusqlite as sql;
 use rusqlite::OptionalExtension;
 use chrono::prelude::*;
+use log::info;
 use serde::{Deserialize, Serialize};
 
-use crate::error;
 use crate::error::Error as Error;
-use crate::store::ItemInfo;
+use crate::store::{Availability, ItemInfo};
+use crate::alert;
 use crate::utils;
 
+/// Schema migrations. Migration n (counting from 1) upgrades the
+/// database to version n. The version is stored as `PRAGMA
+/// user_version`. Never change a released migration; add a new one
+/// instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema
+    "CREATE TABLE wishlist (
+         internal_id INTEGER PRIMARY KEY ASC,
+         store TEXT,
+         id TEXT,
+         name TEXT,
+         url TEXT,
+         alert_price INTEGER,
+         update_interval INTEGER
+     );
+     CREATE TABLE price (
+         time INTEGER,
+         price INTEGER, -- price * 100
+         price_str TEXT,
+         store TEXT,
+         id TEXT,
+         item_id INTEGER,
+         FOREIGN KEY(item_id) REFERENCES wishlist(internal_id)
+     );",
+    // 2: Time of the last price query
+    "ALTER TABLE wishlist ADD COLUMN last_check INTEGER NOT NULL DEFAULT 0;",
+    // 3: Failure tracking
+    "ALTER TABLE wishlist ADD COLUMN failure_count INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE wishlist ADD COLUMN last_error TEXT;",
+    // 4: More alert conditions
+    "ALTER TABLE wishlist ADD COLUMN alert_drop_percent INTEGER;
+     ALTER TABLE wishlist ADD COLUMN alert_all_time_low INTEGER NOT NULL DEFAULT 0;",
+    // 5: The last alert of each item
+    "CREATE TABLE alert (
+         item_id INTEGER PRIMARY KEY,
+         price INTEGER, -- price * 100
+         time INTEGER,
+         FOREIGN KEY(item_id) REFERENCES wishlist(internal_id)
+     );",
+    // 6: Currency of each price. Only ps4-hk was not in USD.
+    "ALTER TABLE price ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';
+     UPDATE price SET currency = 'HKD' WHERE store = 'ps4-hk';",
+    // 7: Availability of each price, and alerts on it
+    "ALTER TABLE price ADD COLUMN availability TEXT NOT NULL DEFAULT 'unknown';
+     ALTER TABLE wishlist ADD COLUMN alert_back_in_stock INTEGER NOT NULL DEFAULT 0;",
+    // 8: Sales
+    "ALTER TABLE price ADD COLUMN regular_price INTEGER; -- price * 100
+     ALTER TABLE price ADD COLUMN discount_percent INTEGER;
+     ALTER TABLE price ADD COLUMN sale_end INTEGER;",
+    // 9: Prices for subscribers, e.g. PlayStation Plus
+    "ALTER TABLE price ADD COLUMN member_price INTEGER; -- price * 100",
+];
+
+/// Columns of the wishlist table read by `rowToItem()`.
+const ITEM_COLUMNS: &str =
+    "internal_id, store, id, name, url, alert_price, update_interval,
+     last_check, failure_count, last_error, alert_drop_percent,
+     alert_all_time_low, alert_back_in_stock";
+
+/// Items cannot be updated more often than this.
+pub const MIN_UPDATE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+#[allow(dead_code)]
 pub enum SqliteFilename { InMemory, File(std::path::PathBuf) }
 
 #[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
@@ -27,10 +91,68 @@ impl ItemKey
     }
 }
 
+/// Changes to the settings of an item. Fields that are `None` are
+/// not changed.
+#[derive(Default, Clone)]
+pub struct ItemUpdate
+{
+    pub name: Option<String>,
+    pub update_interval: Option<Option<time::Duration>>,
+    pub alert_price: Option<Option<u64>>,
+    pub alert_drop_percent: Option<Option<u32>>,
+    pub alert_all_time_low: Option<bool>,
+    pub alert_back_in_stock: Option<bool>,
+}
+
+impl ItemUpdate
+{
+    /// Replace all the alert settings.
+    pub fn fromAlert(settings: &alert::Settings) -> Self
+    {
+        Self {
+            alert_price: Some(settings.alert_price),
+            alert_drop_percent: Some(settings.alert_drop_percent),
+            alert_all_time_low: Some(settings.alert_all_time_low),
+            alert_back_in_stock: Some(settings.alert_back_in_stock),
+            ..Self::default()
+        }
+    }
+
+    fn applyTo(&self, item: &mut ItemInfo)
+    {
+        if let Some(name) = &self.name
+        {
+            item.name = name.clone();
+        }
+        if let Some(dt) = self.update_interval
+        {
+            item.update_interval = dt;
+        }
+        if let Some(p) = self.alert_price
+        {
+            item.alert_price = p;
+        }
+        if let Some(p) = self.alert_drop_percent
+        {
+            item.alert_drop_percent = p;
+        }
+        if let Some(b) = self.alert_all_time_low
+        {
+            item.alert_all_time_low = b;
+        }
+        if let Some(b) = self.alert_back_in_stock
+        {
+            item.alert_back_in_stock = b;
+        }
+    }
+}
+
 pub struct PricePoint
 {
     pub time: DateTime<Utc>,
     pub price: u64,             // Price * 100
+    pub currency: String,
+    pub availability: Availability,
 }
 
 pub struct DataManager
@@ -66,47 +188,81 @@ impl DataManager
         }
     }
 
-    /// Connect to the database. Create database file if not exist.
+    /// Connect to the database. Create database file if not exist,
+    /// and bring the schema up to date.
     pub fn connect(&mut self) -> Result<(), Error>
     {
-        let conn = match &self.filename
+        let mut conn = match &self.filename
         {
             SqliteFilename::File(path) => sql::Connection::open(path).map_err(
-                |e| error!(DataError, "Failed to create database: {}", e))?,
+                |e| error_from!(DataError, e, "Failed to create database"))?,
             SqliteFilename::InMemory =>
                 sql::Connection::open_in_memory().map_err(
-                    |e| error!(DataError, "Failed to create database: {}", e))?,
+                    |e| error_from!(DataError, e,
+                                    "Failed to create database"))?,
         };
+        Self::migrate(&mut conn)?;
         self.connection = Some(conn);
         Ok(())
     }
 
-    pub fn init(&self) -> Result<(), Error>
+    fn schemaVersion(conn: &sql::Connection) -> Result<usize, Error>
     {
-        let conn = self.confirmConnection()?;
-        conn.execute(
-            "CREATE TABLE wishlist (
-                  internal_id INTEGER PRIMARY KEY ASC,
-                  store TEXT,
-                  id TEXT,
-                  name TEXT,
-                  url TEXT,
-                  alert_price INTEGER,
-                  update_interval INTEGER
-                  )", []).map_err(
-            |e| error!(DataError, "Failed to create table: {}", e))?;
+        let version: i64 = conn.pragma_query_value(
+            None, "user_version", |row| row.get(0)).map_err(
+            |e| error_from!(DataError, e, "Failed to get schema version"))?;
+        Ok(version as usize)
+    }
 
-        conn.execute(
-            "CREATE TABLE price (
-                  time INTEGER,
-                  price INTEGER, -- price * 100
-                  price_str TEXT,
-                  store TEXT,
-                  id TEXT,
-                  item_id INTEGER,
-                  FOREIGN KEY(item_id) REFERENCES wishlist(internal_id)
-                  )", []).map_err(
-            |e| error!(DataError, "Failed to create table: {}", e))?;
+    fn setSchemaVersion(conn: &sql::Connection, version: usize) ->
+        Result<(), Error>
+    {
+        conn.pragma_update(None, "user_version", version as i64).map_err(
+            |e| error_from!(DataError, e, "Failed to set schema version"))
+    }
+
+    /// Run all the migrations the database has not seen yet, each in
+    /// its own transaction.
+    fn migrate(conn: &mut sql::Connection) -> Result<(), Error>
+    {
+        let mut version = Self::schemaVersion(conn)?;
+        if version == 0
+        {
+            // Databases created before schema versioning have the
+            // tables of version 1, but no version number.
+            let table_count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'
+                 AND name = 'wishlist'", [], |row| row.get(0)).map_err(
+                |e| error_from!(DataError, e, "Failed to inspect database"))?;
+            if table_count > 0
+            {
+                Self::setSchemaVersion(conn, 1)?;
+                version = 1;
+            }
+        }
+        if version > MIGRATIONS.len()
+        {
+            return Err(error!(
+                DataError, "Database schema version {} is newer than {}",
+                version, MIGRATIONS.len()));
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version)
+        {
+            let tx = conn.transaction().map_err(
+                |e| error_from!(DataError, e, "Failed to start transaction"))?;
+            tx.execute_batch(migration).map_err(
+                |e| error_from!(DataError, e,
+                                "Failed to migrate database to version {}",
+                                i + 1))?;
+            Self::setSchemaVersion(&tx, i + 1)?;
+            tx.commit().map_err(
+                |e| error_from!(DataError, e, "Failed to commit migration"))?;
+        }
+        if version < MIGRATIONS.len()
+        {
+            info!("Database migrated from version {} to {}.", version,
+                  MIGRATIONS.len());
+        }
         Ok(())
     }
 
@@ -129,11 +285,15 @@ impl DataManager
         let conn = self.confirmConnection()?;
         let changed_row_count = conn.execute(
             "INSERT INTO wishlist (store, id, name, url, alert_price,
-                                   update_interval)
-             VALUES (?, ?, ?, ?, ?, ?)",
+                                   update_interval, last_check,
+                                   alert_drop_percent, alert_all_time_low,
+                                   alert_back_in_stock)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             sql::params![&item.store, &item.id, &item.name, &item.url,
-                         alert_price, interval])
-            .map_err(|_| error!(DataError, "Failed to add item"))?;
+                         alert_price, interval, item.last_check.timestamp(),
+                         item.alert_drop_percent, item.alert_all_time_low,
+                         item.alert_back_in_stock])
+            .map_err(|e| error_from!(DataError, e, "Failed to add item"))?;
         if changed_row_count != 1
         {
             return Err(error!(DataError, "Invalid insert happened"));
@@ -145,19 +305,24 @@ impl DataManager
     {
         let rowid: i64 = match self.findItem(item.clone())?
         {
-            None => {return Err(rterr!("Item not found"));},
+            None => {return Err(error!(NotFound, "Item not found"));},
             Some(id) => id,
         };
 
         let conn = self.confirmConnection()?;
         conn.execute("DELETE FROM wishlist WHERE internal_id = ?", [rowid])
-            .map_err(|_| error!(DataError, "Failed to remove item"))?;
+            .map_err(|e| error_from!(DataError, e, "Failed to remove item"))?;
 
         conn.execute("DELETE FROM price WHERE item_id = ?", [rowid])
-            .map_err(|_| error!(DataError, "Failed to remove item prices"))?;
+            .map_err(|e| error_from!(DataError, e,
+                                     "Failed to remove item prices"))?;
+        conn.execute("DELETE FROM alert WHERE item_id = ?", [rowid])
+            .map_err(|e| error_from!(DataError, e,
+                                     "Failed to remove item alert"))?;
         Ok(())
     }
 
+    /// Read a row of `ITEM_COLUMNS` into (internal ID, item).
     fn rowToItem(row: &sql::Row) -> sql::Result<(i64, ItemInfo)>
     {
         let store: String = row.get(1)?;
@@ -183,26 +348,37 @@ impl DataManager
         {
             Some(time::Duration::new(dt as u64, 0))
         };
+        result.last_check = utils::timestampToUtcTime(row.get(7)?);
+        result.failure_count = row.get(8)?;
+        result.last_error = row.get(9)?;
+        result.alert_drop_percent = row.get(10)?;
+        result.alert_all_time_low = row.get(11)?;
+        result.alert_back_in_stock = row.get(12)?;
         Ok((row.get(0)?, result))
     }
 
     pub fn getItems(&self) -> Result<Vec<ItemInfo>, Error>
     {
         let conn = self.confirmConnection()?;
-        let mut cmd = conn.prepare("SELECT * FROM wishlist;").map_err(
-            |_| error!(DataError,
-                       "Failed to compile statement to get all items"))?;
+        let mut cmd = conn.prepare(
+            &format!("SELECT {} FROM wishlist;", ITEM_COLUMNS)).map_err(
+            |e| error_from!(DataError, e,
+                            "Failed to compile statement to get all items"))?;
         let iter = cmd.query_map([], Self::rowToItem).map_err(
-            |e| error!(DataError, "Failed to get all items: {}", e))?;
+            |e| error_from!(DataError, e, "Failed to get all items"))?;
         let mut result: Vec<ItemInfo> = Vec::new();
         for item_pair in iter
         {
             let item_pair = item_pair.map_err(
-                |_| error!(DataError, "Failed to get one of the items"))?;
-            let mut cmd = conn.prepare("SELECT price, price_str, time FROM price
-                WHERE item_id = ? ORDER BY time DESC LIMIT 1").map_err(
-                |_| error!(DataError,
-                           "Failed to compile statement to get price"))?;
+                |e| error_from!(DataError, e,
+                                "Failed to get one of the items"))?;
+            let mut cmd = conn.prepare(
+                "SELECT price, price_str, time, currency, availability,
+                        regular_price, discount_percent, sale_end,
+                        member_price FROM price
+                 WHERE item_id = ? ORDER BY time DESC LIMIT 1").map_err(
+                |e| error_from!(DataError, e,
+                                "Failed to compile statement to get price"))?;
             // Item_pair is (rowid, item).
             result.push(
                 cmd.query_row([item_pair.0], |row| {
@@ -210,8 +386,17 @@ impl DataManager
                     item.price = row.get(0)?;
                     item.price_str = row.get(1)?;
                     item.last_update = utils::timestampToUtcTime(row.get(2)?);
+                    item.currency = row.get(3)?;
+                    item.availability =
+                        Availability::fromCode(&row.get::<_, String>(4)?);
+                    item.regular_price = row.get(5)?;
+                    item.discount_percent = row.get(6)?;
+                    item.sale_end = row.get::<_, Option<i64>>(7)?
+                        .map(utils::timestampToUtcTime);
+                    item.member_price = row.get(8)?;
                     Ok(item)
-                }).map_err(|_| error!(DataError, "Failed to get price"))?);
+                }).map_err(
+                    |e| error_from!(DataError, e, "Failed to get price"))?);
         }
         Ok(result)
     }
@@ -223,44 +408,147 @@ impl DataManager
             "SELECT internal_id FROM wishlist WHERE store = ? AND id = ?",
             [&key.store, &key.id],
             |row| row.get(0)).optional().map_err(
-            |e| error!(DataError, "Failed to find item: {}", e))
+            |e| error_from!(DataError, e, "Failed to find item"))
     }
 
     pub fn addPrice(&self, item: &ItemInfo) -> Result<(), Error>
     {
         let row_id = self.findItem(ItemKey::fromItem(item))?.ok_or_else(
-            || rterr!("Unknown item"))?;
+            || error!(NotFound, "Unknown item"))?;
         let now = Utc::now();
         let conn = self.confirmConnection()?;
         conn.execute("INSERT INTO price (time, price, price_str, store, id,
-                                         item_id)
-                      VALUES (?, ?, ?, ?, ?, ?)",
+                                         item_id, currency, availability,
+                                         regular_price, discount_percent,
+                                         sale_end, member_price)
+                      VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
                      sql::params![now.timestamp(), item.price, item.price_str,
-                                  item.store, item.id, row_id]).map_err(
-            |e| error!(DataError, "Failed to add price: {}", e))?;
+                                  item.store, item.id, row_id, item.currency,
+                                  item.availability.code(), item.regular_price,
+                                  item.discount_percent,
+                                  item.sale_end.map(|t| t.timestamp()),
+                                  item.member_price])
+            .map_err(
+            |e| error_from!(DataError, e, "Failed to add price"))?;
+        Ok(())
+    }
+
+    /// Change the settings of an item. The result is validated before
+    /// saving.
+    pub fn updateItem(&self, key: ItemKey, update: &ItemUpdate) ->
+        Result<(), Error>
+    {
+        let conn = self.confirmConnection()?;
+        let (rowid, mut item) = conn.query_row(
+            &format!("SELECT {} FROM wishlist WHERE store = ? AND id = ?",
+                     ITEM_COLUMNS),
+            [&key.store, &key.id], Self::rowToItem).optional().map_err(
+            |e| error_from!(DataError, e, "Failed to find item"))?
+            .ok_or_else(|| error!(NotFound, "Item not found"))?;
+        update.applyTo(&mut item);
+
+        if item.name.trim().is_empty()
+        {
+            return Err(error!(InvalidInput, "Item name cannot be empty"));
+        }
+        if let Some(dt) = item.update_interval
+        {
+            if dt < MIN_UPDATE_INTERVAL
+            {
+                return Err(error!(
+                    InvalidInput, "Update interval must be at least {} seconds",
+                    MIN_UPDATE_INTERVAL.as_secs()));
+            }
+        }
+        alert::Settings::fromItem(&item).validate()?;
+
+        let alert_price: i64 = item.alert_price.map_or(-1, |p| p as i64);
+        let interval: i64 = item.update_interval.map_or(0, |dt| dt.as_secs() as i64);
+        conn.execute(
+            "UPDATE wishlist SET name = ?, update_interval = ?,
+                 alert_price = ?, alert_drop_percent = ?,
+                 alert_all_time_low = ?, alert_back_in_stock = ?
+             WHERE internal_id = ?",
+            sql::params![item.name, interval, alert_price,
+                         item.alert_drop_percent, item.alert_all_time_low,
+                         item.alert_back_in_stock, rowid])
+            .map_err(|e| error_from!(DataError, e, "Failed to update item"))?;
+        Ok(())
+    }
+
+    /// The last alert sent for an item, if any.
+    pub fn lastAlert(&self, item: ItemKey) -> Result<Option<alert::LastAlert>, Error>
+    {
+        let rowid = self.findItem(item)?.ok_or_else(
+            || error!(NotFound, "Item not found"))?;
+        let conn = self.confirmConnection()?;
+        conn.query_row("SELECT price, time FROM alert WHERE item_id = ?",
+                       [rowid], |row| Ok(alert::LastAlert {
+                           price: row.get(0)?,
+                           time: utils::timestampToUtcTime(row.get(1)?),
+                       })).optional().map_err(
+            |e| error_from!(DataError, e, "Failed to get last alert"))
+    }
+
+    /// Record that an alert was sent for an item at `price`.
+    pub fn recordAlert(&self, item: ItemKey, price: i64, time: DateTime<Utc>) ->
+        Result<(), Error>
+    {
+        let rowid = self.findItem(item)?.ok_or_else(
+            || error!(NotFound, "Item not found"))?;
+        let conn = self.confirmConnection()?;
+        conn.execute("INSERT OR REPLACE INTO alert (item_id, price, time)
+                      VALUES (?, ?, ?)",
+                     sql::params![rowid, price, time.timestamp()]).map_err(
+            |e| error_from!(DataError, e, "Failed to record alert"))?;
+        Ok(())
+    }
+
+    /// Record that the store was queried for the item at `time`. If
+    /// the query failed, `error` is the reason, and the failure count
+    /// of the item is increased; otherwise the failure count is reset.
+    pub fn recordCheck(&self, item: ItemKey, time: DateTime<Utc>,
+                       error: Option<&str>) -> Result<(), Error>
+    {
+        let conn = self.confirmConnection()?;
+        let changed_row_count = conn.execute(
+            "UPDATE wishlist SET last_check = ?,
+                 failure_count = CASE WHEN ? IS NULL THEN 0
+                                      ELSE failure_count + 1 END,
+                 last_error = ?
+             WHERE store = ? AND id = ?",
+            sql::params![time.timestamp(), error, error, item.store, item.id])
+            .map_err(|e| error_from!(DataError, e, "Failed to record check"))?;
+        if changed_row_count != 1
+        {
+            return Err(error!(NotFound, "Item not found"));
+        }
         Ok(())
     }
 
     pub fn priceHistory(&self, item: ItemKey) -> Result<Vec<PricePoint>, Error>
     {
         let rowid = self.findItem(item)?.ok_or_else(
-            || error!(DataError, "Item not found"))?;
+            || error!(NotFound, "Item not found"))?;
         let conn = self.confirmConnection()?;
         let mut cmd = conn.prepare(
-            "SELECT time, price FROM price WHERE item_id = ? ORDER BY time")
-            .map_err(|_| error!(
-                DataError,
+            "SELECT time, price, currency, availability FROM price
+             WHERE item_id = ? ORDER BY time")
+            .map_err(|e| error_from!(
+                DataError, e,
                 "Failed to compile statement to query price history"))?;
         let result: Result<Vec<PricePoint>, rusqlite::Error> =
             cmd.query_map([rowid], |row| {
                 Ok(PricePoint {
                     time: utils::timestampToUtcTime(row.get(0)?),
                     price: row.get(1)?,
+                    currency: row.get(2)?,
+                    availability: Availability::fromCode(&row.get::<_, String>(3)?),
                 })
-            }).map_err(|e| error!(
-                DataError, "Failed to get price history: {}", e))?.collect();
+            }).map_err(|e| error_from!(
+                DataError, e, "Failed to get price history"))?.collect();
         result.map_err(
-            |e| error!(DataError, "Failed to extract price history: {}", e))
+            |e| error_from!(DataError, e, "Failed to extract price history"))
     }
 
 }
@@ -279,7 +567,6 @@ mod tests
     {
         let mut data = DataManager::new(SqliteFilename::InMemory);
         data.connect()?;
-        data.init()?;
         Ok(data)
     }
 
@@ -298,9 +585,104 @@ mod tests
             let i = i?;
             tables.push(i);
         }
-        assert_eq!(tables.len(), 2);
-        assert_eq!(tables[0], "price");
-        assert_eq!(tables[1], "wishlist");
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0], "alert");
+        assert_eq!(tables[1], "price");
+        assert_eq!(tables[2], "wishlist");
+        Ok(())
+    }
+
+    /// Create a database the way it was before schema versioning.
+    fn createBaselineDB(path: &std::path::Path) -> Result<(), AnyError>
+    {
+        let conn = sql::Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE wishlist (
+                  internal_id INTEGER PRIMARY KEY ASC,
+                  store TEXT,
+                  id TEXT,
+                  name TEXT,
+                  url TEXT,
+                  alert_price INTEGER,
+                  update_interval INTEGER
+                  );
+             CREATE TABLE price (
+                  time INTEGER,
+                  price INTEGER, -- price * 100
+                  price_str TEXT,
+                  store TEXT,
+                  id TEXT,
+                  item_id INTEGER,
+                  FOREIGN KEY(item_id) REFERENCES wishlist(internal_id)
+                  );
+             INSERT INTO wishlist (store, id, name, url, alert_price,
+                                   update_interval)
+                 VALUES ('switch', 'id1', 'aaa', 'bbb', -1, 0);
+             INSERT INTO price (time, price, price_str, store, id, item_id)
+                 VALUES (1600000000, 100, '$1.00', 'switch', 'id1', 1);")?;
+        Ok(())
+    }
+
+    #[test]
+    fn schema_version_new_db() -> Result<(), AnyError>
+    {
+        let data = newDataManager()?;
+        let conn = data.confirmConnection()?;
+        assert_eq!(DataManager::schemaVersion(conn)?, MIGRATIONS.len());
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_baseline_db() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("wishlist.db");
+        createBaselineDB(&path)?;
+        let conn = sql::Connection::open(&path)?;
+        conn.execute_batch(
+            "INSERT INTO wishlist (store, id, name, url, alert_price,
+                                   update_interval)
+                 VALUES ('ps4-hk', 'id2', 'ccc', 'ddd', -1, 0);
+             INSERT INTO price (time, price, price_str, store, id, item_id)
+                 VALUES (1600000000, 7800, 'HK$78', 'ps4-hk', 'id2', 2);")?;
+        drop(conn);
+
+        let mut data = DataManager::new(SqliteFilename::File(path.clone()));
+        data.connect()?;
+        let conn = data.confirmConnection()?;
+        let version: i64 = conn.pragma_query_value(
+            None, "user_version", |row| row.get(0))?;
+        assert_eq!(version as usize, MIGRATIONS.len());
+        let items = data.getItems()?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "aaa");
+        assert_eq!(items[0].price, 100);
+        assert_eq!(items[0].currency, "USD");
+        assert_eq!(items[1].currency, "HKD");
+        assert_eq!(items[0].availability, Availability::Unknown);
+        assert!(!items[0].alert_back_in_stock);
+        assert_eq!(data.priceHistory(ItemKey::fromItem(&items[0]))?.len(), 1);
+        assert_eq!(items[0].last_check.timestamp(), 0);
+
+        // Connecting again should not run any migration.
+        let mut data = DataManager::new(SqliteFilename::File(path));
+        data.connect()?;
+        assert_eq!(data.getItems()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_newer_db() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("wishlist.db");
+        let conn = sql::Connection::open(&path)?;
+        conn.pragma_update(None, "user_version",
+                           (MIGRATIONS.len() + 1) as i64)?;
+        drop(conn);
+
+        let mut data = DataManager::new(SqliteFilename::File(path));
+        assert!(data.connect().is_err());
         Ok(())
     }
 
@@ -326,11 +708,20 @@ mod tests
         item1.price = 100;
         item1.price_str = "$1.00".to_owned();
 
+        item1.regular_price = Some(200);
+        item1.discount_percent = Some(50);
+        item1.sale_end = Some(utils::timestampToUtcTime(1600000000));
+        item1.member_price = Some(80);
+
         data.addItem(&item1)?;
         data.addPrice(&item1)?;
         let items = data.getItems()?;
         assert_eq!(items.len(), 1);
         assert_eq!(items[0].name, item1.name);
+        assert_eq!(items[0].regular_price, Some(200));
+        assert_eq!(items[0].discount_percent, Some(50));
+        assert_eq!(items[0].sale_end, item1.sale_end);
+        assert_eq!(items[0].member_price, Some(80));
         Ok(())
     }
 
@@ -349,14 +740,16 @@ mod tests
         item2.name = "xxx".to_owned();
         item2.url = "yyy".to_owned();
         item2.price = 200;
-        item2.price_str = "$2.00".to_owned();
+        item2.price_str = "HK$2.00".to_owned();
+        item2.currency = "HKD".to_owned();
 
         data.addItem(&item1)?;
         data.addPrice(&item1)?;
         data.addItem(&item2)?;
         data.addPrice(&item2)?;
         item2.price = 300;
-        item2.price_str = "$3.00".to_owned();
+        item2.price_str = "HK$3.00".to_owned();
+        item2.availability = Availability::Preorder;
         sleep(time::Duration::new(2, 0));
         data.addPrice(&item2)?;
 
@@ -370,6 +763,12 @@ mod tests
         assert_eq!(items[1].url, item2.url);
         assert_eq!(items[1].price, item2.price);
         assert_eq!(items[1].price_str, item2.price_str);
+        assert_eq!(items[1].currency, "HKD");
+        assert_eq!(items[0].availability, Availability::Unknown);
+        assert_eq!(items[1].availability, Availability::Preorder);
+        let history = data.priceHistory(ItemKey::fromItem(&item2))?;
+        assert_eq!(history[0].availability, Availability::Unknown);
+        assert_eq!(history[1].availability, Availability::Preorder);
 
         let conn = data.confirmConnection()?;
         let count_item2_prices: i64 = conn.query_row(
@@ -380,6 +779,134 @@ mod tests
         Ok(())
     }
 
+    #[test]
+    fn record_check() -> Result<(), AnyError>
+    {
+        let data = newDataManager()?;
+        let mut item = ItemInfo::new("switch", "id1");
+        item.price = 100;
+        data.addItem(&item)?;
+        data.addPrice(&item)?;
+        assert_eq!(data.getItems()?[0].last_check.timestamp(),
+                   item.last_check.timestamp());
+
+        let key = ItemKey::fromItem(&item);
+        let t = utils::timestampToUtcTime(1600000000);
+        data.recordCheck(key.clone(), t, Some("oops"))?;
+        data.recordCheck(key.clone(), t, Some("oops again"))?;
+        let items = data.getItems()?;
+        assert_eq!(items[0].last_check, t);
+        assert_eq!(items[0].failure_count, 2);
+        assert_eq!(items[0].last_error.as_deref(), Some("oops again"));
+
+        data.recordCheck(key, t, None)?;
+        let items = data.getItems()?;
+        assert_eq!(items[0].failure_count, 0);
+        assert_eq!(items[0].last_error, None);
+
+        assert!(data.recordCheck(
+            ItemKey { store: "switch".to_owned(), id: "id2".to_owned() }, t,
+            None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn update_item() -> Result<(), AnyError>
+    {
+        let data = newDataManager()?;
+        let mut item = ItemInfo::new("switch", "id1");
+        item.name = "aaa".to_owned();
+        item.alert_price = Some(500);
+        data.addItem(&item)?;
+        data.addPrice(&item)?;
+        let key = ItemKey::fromItem(&item);
+        assert_eq!(alert::Settings::fromItem(&data.getItems()?[0]),
+                   alert::Settings { alert_price: Some(500),
+                                     ..alert::Settings::default() });
+
+        let settings = alert::Settings {
+            alert_price: None,
+            alert_drop_percent: Some(20),
+            alert_all_time_low: true,
+            alert_back_in_stock: true,
+        };
+        data.updateItem(key.clone(), &ItemUpdate::fromAlert(&settings))?;
+        assert_eq!(alert::Settings::fromItem(&data.getItems()?[0]), settings);
+
+        data.updateItem(key.clone(), &ItemUpdate {
+            name: Some("bbb".to_owned()),
+            update_interval: Some(Some(time::Duration::from_secs(7200))),
+            ..ItemUpdate::default()
+        })?;
+        let item = &data.getItems()?[0];
+        assert_eq!(item.name, "bbb");
+        assert_eq!(item.update_interval, Some(time::Duration::from_secs(7200)));
+        // Untouched
+        assert_eq!(alert::Settings::fromItem(item), settings);
+
+        data.updateItem(key.clone(), &ItemUpdate {
+            update_interval: Some(None),
+            ..ItemUpdate::default()
+        })?;
+        assert_eq!(data.getItems()?[0].update_interval, None);
+
+        assert!(matches!(
+            data.updateItem(ItemKey { store: "switch".to_owned(),
+                                      id: "id2".to_owned() },
+                            &ItemUpdate::fromAlert(&settings)),
+            Err(Error::NotFound(..))));
+        Ok(())
+    }
+
+    #[test]
+    fn update_item_validation() -> Result<(), AnyError>
+    {
+        let data = newDataManager()?;
+        let mut item = ItemInfo::new("switch", "id1");
+        item.name = "aaa".to_owned();
+        data.addItem(&item)?;
+        data.addPrice(&item)?;
+        let key = ItemKey::fromItem(&item);
+        let invalid = [
+            ItemUpdate { name: Some(" ".to_owned()), ..ItemUpdate::default() },
+            ItemUpdate { update_interval: Some(Some(time::Duration::from_secs(5))),
+                         ..ItemUpdate::default() },
+            ItemUpdate { alert_drop_percent: Some(Some(0)),
+                         ..ItemUpdate::default() },
+        ];
+        for update in &invalid
+        {
+            assert!(matches!(data.updateItem(key.clone(), update),
+                             Err(Error::InvalidInput(..))));
+        }
+        let item = &data.getItems()?[0];
+        assert_eq!(item.name, "aaa");
+        assert_eq!(item.update_interval, None);
+        assert_eq!(item.alert_drop_percent, None);
+        Ok(())
+    }
+
+    #[test]
+    fn record_alert() -> Result<(), AnyError>
+    {
+        let data = newDataManager()?;
+        let item = ItemInfo::new("switch", "id1");
+        data.addItem(&item)?;
+        let key = ItemKey::fromItem(&item);
+        assert_eq!(data.lastAlert(key.clone())?, None);
+
+        let time = utils::timestampToUtcTime(1600000000);
+        data.recordAlert(key.clone(), 1500, time)?;
+        data.recordAlert(key.clone(), 1000, time)?;
+        assert_eq!(data.lastAlert(key.clone())?,
+                   Some(alert::LastAlert { price: 1000, time }));
+
+        data.removeItem(key.clone())?;
+        data.addItem(&item)?;
+        assert_eq!(data.lastAlert(key)?, None);
+        Ok(())
+    }
+
     #[test]
     fn remove_item() -> Result<(), AnyError>
     {
@@ -1,5 +1,6 @@
 use std::error::Error as StdError;
 use std::fmt;
+use std::sync::Arc;
 
 #[macro_export]
 macro_rules! error
@@ -7,13 +8,34 @@ macro_rules! error
     ( $err_type:ident, $msg:literal ) =>
     {
         {
-            Error::$err_type(String::from($msg))
+            Error::$err_type(String::from($msg), None)
         }
     };
     ( $err_type:ident, $msg:literal $(, $x:expr)+) =>
     {
         {
-            Error::$err_type(format!($msg $(, $x)+))
+            Error::$err_type(format!($msg $(, $x)+), None)
+        }
+    };
+}
+
+// Construct an error that keeps its cause, e.g.
+// error_from!(Network, e, "Failed to get {}", url).
+#[macro_export]
+macro_rules! error_from
+{
+    ( $err_type:ident, $source:expr, $msg:literal ) =>
+    {
+        {
+            Error::$err_type(String::from($msg),
+                             Some(std::sync::Arc::new($source)))
+        }
+    };
+    ( $err_type:ident, $source:expr, $msg:literal $(, $x:expr)+) =>
+    {
+        {
+            Error::$err_type(format!($msg $(, $x)+),
+                             Some(std::sync::Arc::new($source)))
         }
     };
 }
@@ -28,32 +50,131 @@ macro_rules! rterr
     };
 }
 
+/// The underlying error that caused an `Error`.
+pub type Source = Arc<dyn StdError + Send + Sync>;
+
+/// Each variant has a message, and optionally the error that caused
+/// it.
+// The “Error” suffix of the older variants is kept.
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Error
 {
+    /// Anything that does not fit in the other variants.
+    RuntimeError(String, Option<Source>),
+
     /// An error from the underlying data source. This could be a
     /// database connection issue, or disk I/O failure, or invalid
     /// data from the data source, etc. This is not a “logic error”
     /// such as an error from generating SQL statement due to invalid
     /// backlinks.
-    RuntimeError(String),
+    DataError(String, Option<Source>),
+
+    /// The requested item, store, etc. does not exist.
+    NotFound(String, Option<Source>),
+
+    /// Failed to talk to a store.
+    Network(String, Option<Source>),
+
+    /// The response from a store is malformed, e.g. invalid JSON or
+    /// an unparsable price.
+    Parse(String, Option<Source>),
+
+    /// The response from a store is well-formed, but does not have
+    /// the expected structure. Usually this means the store changed
+    /// its website or API.
+    StoreChanged(String, Option<Source>),
+
+    /// Invalid configuration.
+    Config(String, Option<Source>),
+
+    /// Invalid input from the user, e.g. a bad command line argument
+    /// or API request.
+    InvalidInput(String, Option<Source>),
+}
+
+impl Error
+{
+    /// A short machine-readable name of the variant.
+    pub fn kind(&self) -> &'static str
+    {
+        match self
+        {
+            Error::RuntimeError(..) => "runtime",
+            Error::DataError(..) => "data",
+            Error::NotFound(..) => "not_found",
+            Error::Network(..) => "network",
+            Error::Parse(..) => "parse",
+            Error::StoreChanged(..) => "store_changed",
+            Error::Config(..) => "config",
+            Error::InvalidInput(..) => "invalid_input",
+        }
+    }
 
-    DataError(String),
+    fn parts(&self) -> (&str, &Option<Source>)
+    {
+        match self
+        {
+            Error::RuntimeError(msg, src) |
+            Error::DataError(msg, src) |
+            Error::NotFound(msg, src) |
+            Error::Network(msg, src) |
+            Error::Parse(msg, src) |
+            Error::StoreChanged(msg, src) |
+            Error::Config(msg, src) |
+            Error::InvalidInput(msg, src) => (msg, src),
+        }
+    }
 }
 
 impl fmt::Display for Error
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
     {
-        match self
+        let prefix = match self
+        {
+            Error::RuntimeError(..) => "Runtime error",
+            Error::DataError(..) => "Data error",
+            Error::NotFound(..) => "Not found",
+            Error::Network(..) => "Network error",
+            Error::Parse(..) => "Parse error",
+            Error::StoreChanged(..) => "Store changed",
+            Error::Config(..) => "Config error",
+            Error::InvalidInput(..) => "Invalid input",
+        };
+        match self.parts()
         {
-            Error::RuntimeError(msg) => write!(f, "Runtime error: {}", msg),
-            Error::DataError(msg) => write!(f, "Data error: {}", msg),
+            (msg, Some(src)) => write!(f, "{}: {}: {}", prefix, msg, src),
+            (msg, None) => write!(f, "{}: {}", prefix, msg),
         }
     }
 }
 
 impl StdError for Error
 {
-    fn source(&self) -> Option<&(dyn StdError + 'static)> {None}
+    fn source(&self) -> Option<&(dyn StdError + 'static)>
+    {
+        self.parts().1.as_ref().map(|src| src.as_ref() as &(dyn StdError + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn source_chain()
+    {
+        let cause = "abc".parse::<i64>().unwrap_err();
+        let err = error_from!(Parse, cause.clone(), "Failed to parse {}", "abc");
+        assert_eq!(err.kind(), "parse");
+        assert_eq!(err.to_string(),
+                   format!("Parse error: Failed to parse abc: {}", cause));
+        assert_eq!(err.source().unwrap().to_string(), cause.to_string());
+
+        let err = error!(NotFound, "Item not found");
+        assert_eq!(err.to_string(), "Not found: Item not found");
+        assert!(err.source().is_none());
+    }
 }
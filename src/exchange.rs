@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::config::{ConfigParams, ExchangeRateParams};
+use crate::http;
+use crate::utils;
+
+/// Exchange rates against a base currency.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Rates
+{
+    /// ISO 4217 code of the base currency.
+    // open.er-api.com calls it “base_code”.
+    #[serde(alias = "base_code")]
+    pub base: String,
+    /// How much of each currency one unit of `base` buys.
+    #[serde(default)]
+    pub rates: HashMap<String, f64>,
+}
+
+impl Rates
+{
+    /// No rates at all. Only `base` can be converted, to itself.
+    pub fn empty(base: &str) -> Self
+    {
+        Self { base: base.to_owned(), rates: HashMap::new() }
+    }
+
+    fn rate(&self, currency: &str) -> Option<f64>
+    {
+        if currency == self.base
+        {
+            Some(1.0)
+        }
+        else
+        {
+            self.rates.get(currency).cloned().filter(|r| *r > 0.0)
+        }
+    }
+
+    /// Convert `price` (price * 100) from currency `from` to `to`.
+    /// The result is rounded to the smallest unit of `to`.
+    pub fn convert(&self, price: i64, from: &str, to: &str) ->
+        Result<i64, Error>
+    {
+        if from == to
+        {
+            return Ok(price);
+        }
+        let rate_from = self.rate(from).ok_or_else(
+            || error!(NotFound, "No exchange rate for {}", from))?;
+        let rate_to = self.rate(to).ok_or_else(
+            || error!(NotFound, "No exchange rate for {}", to))?;
+        let converted = price as f64 * rate_to / rate_from;
+        if utils::currencyDecimals(to) == 0
+        {
+            Ok((converted / 100.0).round() as i64 * 100)
+        }
+        else
+        {
+            Ok(converted.round() as i64)
+        }
+    }
+}
+
+/// A source of exchange rates.
+#[async_trait]
+pub trait RateProvider: Send + Sync
+{
+    /// A short description of the source, used in logs.
+    fn name(&self) -> String;
+    async fn rates(&self) -> Result<Rates, Error>;
+}
+
+/// Used when no source is configured.
+struct NoRates
+{
+    base: String,
+}
+
+#[async_trait]
+impl RateProvider for NoRates
+{
+    fn name(&self) -> String { String::from("none") }
+
+    async fn rates(&self) -> Result<Rates, Error>
+    {
+        Ok(Rates::empty(&self.base))
+    }
+}
+
+/// Read the rates from a TOML file. The file is read every time, so
+/// that it can be edited without restarting the service.
+struct FileRates
+{
+    path: String,
+}
+
+#[async_trait]
+impl RateProvider for FileRates
+{
+    fn name(&self) -> String { format!("file {}", self.path) }
+
+    async fn rates(&self) -> Result<Rates, Error>
+    {
+        let content = tokio::fs::read_to_string(&self.path).await.map_err(
+            |e| error_from!(Config, e, "Failed to read exchange rates from {}",
+                            self.path))?;
+        toml::from_str(&content).map_err(
+            |e| error_from!(Config, e, "Failed to parse exchange rates in {}",
+                            self.path))
+    }
+}
+
+/// Fetch the rates as JSON from a URL, and cache them for a while.
+struct HttpRates
+{
+    url: String,
+    http: Arc<http::Client>,
+    cache_duration: Duration,
+    cache: Mutex<Option<(Instant, Rates)>>,
+}
+
+impl HttpRates
+{
+    fn new(url: &str, http: Arc<http::Client>, cache_duration: Duration) ->
+        Self
+    {
+        Self { url: url.to_owned(), http, cache_duration,
+               cache: Mutex::new(None) }
+    }
+
+    async fn fetch(&self) -> Result<Rates, Error>
+    {
+        let content = self.http.get(&self.url).await?;
+        serde_json::from_str(&content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse exchange rates from {}",
+                            self.url))
+    }
+}
+
+#[async_trait]
+impl RateProvider for HttpRates
+{
+    fn name(&self) -> String { format!("HTTP {}", self.url) }
+
+    /// If fetching fails, fall back to the last fetched rates, however
+    /// old they are.
+    async fn rates(&self) -> Result<Rates, Error>
+    {
+        let cached = self.cache.lock().unwrap().clone();
+        if let Some((time, rates)) = &cached
+        {
+            if time.elapsed() < self.cache_duration
+            {
+                return Ok(rates.clone());
+            }
+        }
+        match self.fetch().await
+        {
+            Ok(rates) =>
+            {
+                *self.cache.lock().unwrap() = Some((Instant::now(), rates.clone()));
+                Ok(rates)
+            },
+            Err(e) => match cached
+            {
+                Some((_, rates)) =>
+                {
+                    warn!("{}. Using old exchange rates.", e);
+                    Ok(rates)
+                },
+                None => Err(e),
+            },
+        }
+    }
+}
+
+/// The exchange rate source in the config. HTTP sources use `http`.
+pub fn fromConfig(conf: &ConfigParams, http: Arc<http::Client>) ->
+    Box<dyn RateProvider>
+{
+    match &conf.exchange_rates
+    {
+        None => Box::new(NoRates { base: conf.home_currency.clone() }),
+        Some(ExchangeRateParams::File { path }) =>
+            Box::new(FileRates { path: path.clone() }),
+        Some(ExchangeRateParams::Http { url, cache_sec }) =>
+            Box::new(HttpRates::new(url, http, Duration::from_secs(*cache_sec))),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::fs;
+    use crate::config::HttpParams;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use warp::Filter;
+
+    type AnyError = Box<dyn std::error::Error>;
+
+    fn rates() -> Rates
+    {
+        let mut rates = HashMap::new();
+        rates.insert(String::from("HKD"), 7.8);
+        rates.insert(String::from("JPY"), 150.0);
+        rates.insert(String::from("EUR"), 0.8);
+        Rates { base: String::from("USD"), rates }
+    }
+
+    /// Serve `body` with `status`. Return the URL and the number of
+    /// requests received so far.
+    async fn serveRates(status: u16, body: &'static str) ->
+        (String, Arc<AtomicUsize>)
+    {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let route = warp::any().map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::with_status(
+                body, warp::http::StatusCode::from_u16(status).unwrap())
+        });
+        let (addr, server) = warp::serve(route)
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/latest/USD", addr), count)
+    }
+
+    fn newHttpRates(url: &str, cache_duration: Duration) -> HttpRates
+    {
+        let http = Arc::new(http::Client::new(&HttpParams::default()).unwrap());
+        HttpRates::new(url, http, cache_duration)
+    }
+
+    #[test]
+    fn convert() -> Result<(), Error>
+    {
+        let r = rates();
+        assert_eq!(r.convert(1999, "USD", "USD")?, 1999);
+        assert_eq!(r.convert(1000, "USD", "HKD")?, 7800);
+        assert_eq!(r.convert(7800, "HKD", "USD")?, 1000);
+        assert_eq!(r.convert(1000, "HKD", "EUR")?, 103);
+        // Yen has no cents.
+        assert_eq!(r.convert(1999, "USD", "JPY")?, 299900);
+        assert_eq!(r.convert(150000, "JPY", "EUR")?, 800);
+        assert!(r.convert(1000, "GBP", "USD").is_err());
+        assert!(r.convert(1000, "USD", "GBP").is_err());
+        assert_eq!(Rates::empty("USD").convert(1000, "USD", "USD")?, 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn file_rates() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("rates.toml");
+        fs::write(&path, "base = \"USD\"\n[rates]\nHKD = 7.8\nJPY = 150\nEUR = 0.8\n")?;
+        let provider = FileRates { path: path.to_string_lossy().into_owned() };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(rt.block_on(provider.rates())?, rates());
+
+        fs::write(&path, "rates = 1")?;
+        assert_eq!(rt.block_on(provider.rates()).unwrap_err().kind(), "config");
+        Ok(())
+    }
+
+    #[test]
+    fn http_rates() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, count) = serveRates(200, r#"{"result": "success",
+                "base_code": "USD", "rates": {"USD": 1, "HKD": 7.8,
+                "JPY": 150, "EUR": 0.8}}"#).await;
+            let provider = newHttpRates(&url, Duration::from_secs(3600));
+            let mut expected = rates();
+            expected.rates.insert(String::from("USD"), 1.0);
+            assert_eq!(provider.rates().await?, expected);
+            // Cached
+            assert_eq!(provider.rates().await?, expected);
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn http_rates_fallback() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, _) = serveRates(404, "").await;
+            let provider = newHttpRates(&url, Duration::from_secs(0));
+            assert_eq!(provider.rates().await.unwrap_err().kind(), "not_found");
+
+            *provider.cache.lock().unwrap() = Some((Instant::now(), rates()));
+            assert_eq!(provider.rates().await?, rates());
+
+            let (url, _) = serveRates(200, "not json").await;
+            let provider = newHttpRates(&url, Duration::from_secs(0));
+            assert_eq!(provider.rates().await.unwrap_err().kind(), "parse");
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use reqwest;
+
+use crate::config::{HttpParams, StoreHttpParams};
+use crate::error::Error;
+use crate::utils;
+
+/// The HTTP client shared by all the stores. It keeps a pool of
+/// connections, so there should only be one.
+pub struct Client
+{
+    client: reqwest::Client,
+    user_agents: Vec<String>,
+    next_agent: AtomicUsize,
+    stores: HashMap<String, StoreHttpParams>,
+}
+
+impl Client
+{
+    pub fn new(params: &HttpParams) -> Result<Self, Error>
+    {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(params.timeout_sec))
+            .connect_timeout(Duration::from_secs(params.connect_timeout_sec));
+        if let Some(proxy) = &params.proxy
+        {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(
+                |e| error_from!(Config, e, "Invalid proxy: {}", proxy))?);
+        }
+        let client = builder.build().map_err(
+            |e| error_from!(Config, e, "Failed to create HTTP client"))?;
+        Ok(Self {
+            client,
+            user_agents: params.user_agents.clone(),
+            next_agent: AtomicUsize::new(0),
+            stores: params.stores.clone(),
+        })
+    }
+
+    /// Take turns with the user agents.
+    fn userAgent(&self) -> Option<&str>
+    {
+        if self.user_agents.is_empty()
+        {
+            return None;
+        }
+        let i = self.next_agent.fetch_add(1, Ordering::Relaxed);
+        Some(&self.user_agents[i % self.user_agents.len()])
+    }
+
+    fn withUserAgent(&self, req: reqwest::RequestBuilder) ->
+        reqwest::RequestBuilder
+    {
+        match self.userAgent()
+        {
+            Some(agent) => req.header("User-Agent", agent),
+            None => req,
+        }
+    }
+
+    /// A GET request that is not for a store.
+    pub fn request(&self, url: &str) -> reqwest::RequestBuilder
+    {
+        self.withUserAgent(self.client.get(url))
+    }
+
+    /// A POST request that is not for a store, e.g. a notification.
+    pub fn post(&self, url: &str) -> reqwest::RequestBuilder
+    {
+        self.withUserAgent(self.client.post(url))
+    }
+
+    pub async fn get(&self, url: &str) -> Result<String, Error>
+    {
+        utils::queryRequest(self.request(url)).await
+    }
+
+    /// The client to query `store` with, using the settings of the
+    /// store, or of its `family` if the store has none.
+    pub fn forStore(self: &Arc<Self>, store: &str, family: &str) ->
+        StoreClient
+    {
+        let params = self.stores.get(store).or_else(|| self.stores.get(family))
+            .cloned().unwrap_or_default();
+        StoreClient { client: self.clone(), params }
+    }
+}
+
+/// The shared client, with the settings of a store.
+#[derive(Clone)]
+pub struct StoreClient
+{
+    client: Arc<Client>,
+    params: StoreHttpParams,
+}
+
+impl StoreClient
+{
+    /// Apply the base URL override to `url`.
+    fn url(&self, url: &str) -> String
+    {
+        let base = match &self.params.base_url
+        {
+            Some(base) => base.trim_end_matches('/'),
+            None => return url.to_owned(),
+        };
+        match reqwest::Url::parse(url)
+        {
+            Ok(u) => match u.query()
+            {
+                Some(q) => format!("{}{}?{}", base, u.path(), q),
+                None => format!("{}{}", base, u.path()),
+            },
+            Err(_) => url.to_owned(),
+        }
+    }
+
+    /// A GET request to `url`, with the headers and cookies of the
+    /// store.
+    pub fn request(&self, url: &str) -> reqwest::RequestBuilder
+    {
+        let mut req = self.client.request(&self.url(url));
+        for (name, value) in &self.params.headers
+        {
+            req = req.header(name.as_str(), value.as_str());
+        }
+        if !self.params.cookies.is_empty()
+        {
+            let mut cookies: Vec<String> = self.params.cookies.iter()
+                .map(|(name, value)| format!("{}={}", name, value)).collect();
+            cookies.sort();
+            req = req.header("Cookie", cookies.join("; "));
+        }
+        req
+    }
+
+    /// Send a request from `request()`, and return the response body.
+    pub async fn send(&self, req: reqwest::RequestBuilder) ->
+        Result<String, Error>
+    {
+        utils::queryRequest(req).await
+    }
+
+    pub async fn get(&self, url: &str) -> Result<String, Error>
+    {
+        self.send(self.request(url)).await
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Mutex;
+    use warp::Filter;
+
+    type AnyError = Box<dyn std::error::Error>;
+
+    /// Reply to every request with its headers as JSON.
+    async fn echoHeaders() -> (String, Arc<Mutex<Vec<String>>>)
+    {
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let received = paths.clone();
+        let route = warp::path::full().and(warp::header::headers_cloned())
+            .map(move |path: warp::path::FullPath,
+                       headers: warp::http::HeaderMap| {
+                received.lock().unwrap().push(path.as_str().to_owned());
+                let headers: HashMap<String, String> = headers.iter()
+                    .map(|(k, v)| (k.to_string(),
+                                   v.to_str().unwrap_or("").to_owned()))
+                    .collect();
+                warp::reply::json(&headers)
+            });
+        let (addr, server) = warp::serve(route)
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}", addr), paths)
+    }
+
+    fn headersOf(body: &str) -> HashMap<String, String>
+    {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn store_settings() -> Result<(), AnyError>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, paths) = echoHeaders().await;
+            let mut params = HttpParams::default();
+            params.user_agents = vec![String::from("agent-1"),
+                                      String::from("agent-2")];
+            let mut amazon = StoreHttpParams::default();
+            amazon.headers.insert(String::from("Accept-Language"),
+                                  String::from("en-US"));
+            amazon.cookies.insert(String::from("session-id"), String::from("123"));
+            amazon.cookies.insert(String::from("i18n-prefs"), String::from("USD"));
+            amazon.base_url = Some(format!("{}/", url));
+            params.stores.insert(String::from("amazon"), amazon);
+            params.stores.insert(String::from("ps4-us"), StoreHttpParams {
+                base_url: Some(url.clone()),
+                ..StoreHttpParams::default()
+            });
+            let client = Arc::new(Client::new(&params)?);
+
+            // The family settings
+            let headers = headersOf(&client.forStore("amazon-uk", "amazon")
+                                    .get("https://amazon.co.uk/dp/B0?th=1").await?);
+            assert_eq!(headers["accept-language"], "en-US");
+            assert_eq!(headers["cookie"], "i18n-prefs=USD; session-id=123");
+            assert_eq!(headers["user-agent"], "agent-1");
+
+            // The store settings
+            let headers = headersOf(&client.forStore("ps4-us", "ps4")
+                                    .get("https://store.playstation.com/x").await?);
+            assert!(!headers.contains_key("cookie"));
+            assert_eq!(headers["user-agent"], "agent-2");
+
+            assert_eq!(*paths.lock().unwrap(), vec!["/dp/B0", "/x"]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn no_override()
+    {
+        let client = Arc::new(Client::new(&HttpParams::default()).unwrap());
+        let url = "https://www.amazon.com/gp/product/B08164VTWH/?th=1";
+        assert_eq!(client.forStore("amazon-us", "amazon").url(url), url);
+    }
+
+    #[test]
+    fn invalid_proxy()
+    {
+        let mut params = HttpParams::default();
+        params.proxy = Some(String::from("not a proxy"));
+        assert_eq!(Client::new(&params).err().map(|e| e.kind()), Some("config"));
+        params.proxy = Some(String::from("socks5://127.0.0.1:1080"));
+        assert!(Client::new(&params).is_ok());
+    }
+
+    #[test]
+    fn timeout()
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // Accept connections, but never reply.
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+                .unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut sockets = Vec::new();
+                while let Ok((socket, _)) = listener.accept().await
+                {
+                    sockets.push(socket);
+                }
+            });
+            let mut params = HttpParams::default();
+            params.timeout_sec = 1;
+            let client = Client::new(&params).unwrap();
+            let req = client.request(&format!("http://{}/", addr));
+            let start = std::time::Instant::now();
+            assert!(req.send().await.is_err());
+            assert!(start.elapsed() < Duration::from_secs(5));
+        });
+    }
+}
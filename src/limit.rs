@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+use crate::config::{ConfigParams, StoreLimitParams};
+
+/// Throttles the queries to one store (or store family).
+struct StoreLimiter
+{
+    slots: Arc<Semaphore>,
+    /// Minimal time between the starts of two queries.
+    min_gap: Duration,
+    /// Time to wait after a query finishes.
+    delay: Duration,
+    /// Earliest time the next query could start.
+    next_start: Mutex<Instant>,
+}
+
+impl StoreLimiter
+{
+    fn new(params: &StoreLimitParams) -> Self
+    {
+        let min_gap = match params.requests_per_minute
+        {
+            Some(n) if n > 0 => Duration::from_secs(60) / n,
+            _ => Duration::ZERO,
+        };
+        Self {
+            slots: Arc::new(Semaphore::new(params.max_concurrent.max(1))),
+            min_gap,
+            delay: Duration::from_millis(params.delay_ms),
+            next_start: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Reserve a start time for a query, and return it.
+    fn reserveStart(&self) -> Instant
+    {
+        let mut next_start = self.next_start.lock().unwrap();
+        let start = (*next_start).max(Instant::now());
+        *next_start = start + self.min_gap;
+        start
+    }
+
+    fn finish(&self)
+    {
+        let mut next_start = self.next_start.lock().unwrap();
+        *next_start = (*next_start).max(Instant::now() + self.delay);
+    }
+}
+
+/// Permission to query a store. The query is considered finished
+/// when this is dropped.
+pub struct Permit<'a>
+{
+    store: Arc<StoreLimiter>,
+    _store_slot: OwnedSemaphorePermit,
+    _global_slot: SemaphorePermit<'a>,
+}
+
+impl Drop for Permit<'_>
+{
+    fn drop(&mut self)
+    {
+        self.store.finish();
+    }
+}
+
+/// Limits the number of concurrent store queries, both in total and
+/// per store, and spaces out the queries to the same store.
+pub struct Limiter
+{
+    global: Semaphore,
+    params: HashMap<String, StoreLimitParams>,
+    stores: Mutex<HashMap<String, Arc<StoreLimiter>>>,
+}
+
+impl Limiter
+{
+    pub fn new(conf: &ConfigParams) -> Self
+    {
+        Self {
+            global: Semaphore::new(conf.max_concurrent_queries.max(1)),
+            params: conf.store_limits.clone(),
+            stores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Find the limiter of a store. A limit configured for the store
+    /// name takes precedence over one for the store family.
+    fn storeLimiter(&self, store: &str, family: &str) -> Arc<StoreLimiter>
+    {
+        let (key, params) = if let Some(p) = self.params.get(store)
+        {
+            (store, p.clone())
+        }
+        else if let Some(p) = self.params.get(family)
+        {
+            (family, p.clone())
+        }
+        else
+        {
+            (store, StoreLimitParams::default())
+        };
+        let mut stores = self.stores.lock().unwrap();
+        stores.entry(key.to_owned())
+            .or_insert_with(|| Arc::new(StoreLimiter::new(&params))).clone()
+    }
+
+    /// Wait until a query to `store`, which belongs to `family`, is
+    /// allowed.
+    pub async fn acquire(&self, store: &str, family: &str) -> Permit<'_>
+    {
+        let limiter = self.storeLimiter(store, family);
+        // The semaphores are never closed.
+        let store_slot = limiter.slots.clone().acquire_owned().await.unwrap();
+        tokio::time::sleep_until(limiter.reserveStart()).await;
+        let global_slot = self.global.acquire().await.unwrap();
+        Permit {
+            store: limiter,
+            _store_slot: store_slot,
+            _global_slot: global_slot,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn limiter(limits: &[(&str, usize, Option<u32>, u64)]) -> Limiter
+    {
+        let mut conf = ConfigParams::default();
+        conf.max_concurrent_queries = 3;
+        conf.store_limits = limits.iter().map(
+            |(name, max_concurrent, rpm, delay_ms)| {
+                (name.to_string(), StoreLimitParams {
+                    max_concurrent: *max_concurrent,
+                    requests_per_minute: *rpm,
+                    delay_ms: *delay_ms,
+                })
+            }).collect();
+        Limiter::new(&conf)
+    }
+
+    /// Run `n` fake queries of `duration` to each store at the same
+    /// time. Return the maximum number of concurrent queries, in
+    /// total and of the first store.
+    fn runQueries(limiter: &Limiter, stores: &[(&str, &str)], n: usize,
+                  duration: Duration) -> (usize, usize)
+    {
+        let running = AtomicUsize::new(0);
+        let running_first = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let max_running_first = AtomicUsize::new(0);
+        let counters = (&running, &running_first, &max_running,
+                        &max_running_first);
+        let queries = stores.iter().enumerate()
+            .flat_map(|(i, store)| std::iter::repeat_n((i, store), n))
+            .map(move |(i, &(store, family))| async move {
+                let (running, running_first, max_running, max_running_first) =
+                    counters;
+                let _permit = limiter.acquire(store, family).await;
+                let count = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(count, Ordering::SeqCst);
+                if i == 0
+                {
+                    let count = running_first.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running_first.fetch_max(count, Ordering::SeqCst);
+                }
+                tokio::time::sleep(duration).await;
+                if i == 0
+                {
+                    running_first.fetch_sub(1, Ordering::SeqCst);
+                }
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(futures::future::join_all(queries));
+        (max_running.load(Ordering::SeqCst),
+         max_running_first.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn concurrency()
+    {
+        let l = limiter(&[("amazon", 1, None, 0)]);
+        let (total, first) = runQueries(
+            &l, &[("amazon-us", "amazon"), ("ps4-us", "ps4"), ("ps4-hk", "ps4")],
+            4, Duration::from_millis(50));
+        assert_eq!(first, 1);
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn store_name_overrides_family()
+    {
+        let l = limiter(&[("ps4", 1, None, 0), ("ps4-us", 3, None, 0)]);
+        let (_, first) = runQueries(&l, &[("ps4-us", "ps4")], 4,
+                                    Duration::from_millis(50));
+        assert_eq!(first, 3);
+    }
+
+    #[test]
+    fn delay()
+    {
+        let l = limiter(&[("amazon", 2, None, 100)]);
+        let begin = std::time::Instant::now();
+        runQueries(&l, &[("amazon-us", "amazon")], 3, Duration::ZERO);
+        // 2 queries run right away, then wait for the delay.
+        assert!(begin.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn rate_limit()
+    {
+        // One query every 50ms.
+        let l = limiter(&[("amazon", 3, Some(1200), 0)]);
+        let begin = std::time::Instant::now();
+        runQueries(&l, &[("amazon-us", "amazon")], 3, Duration::ZERO);
+        assert!(begin.elapsed() >= Duration::from_millis(100));
+    }
+}
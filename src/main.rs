@@ -1,8 +1,10 @@
 #![allow(non_snake_case)]
+#![allow(clippy::single_component_path_imports)]
 
 use std::path::PathBuf;
 use std::process::exit;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use log::{debug,info,warn};
 use log::error as log_error;
@@ -17,6 +19,13 @@ mod web;
 mod data;
 mod config;
 mod middle;
+mod limit;
+mod notify;
+mod alert;
+mod exchange;
+mod http;
+#[cfg(test)]
+mod testing;
 
 use crate::error::Error;
 
@@ -38,13 +47,11 @@ fn findConfigFile() -> Option<PathBuf>
     }
 
     // /etc
-    if let Ok(mut path) = PathBuf::from_str("/etc")
+    let mut path = PathBuf::from("/etc");
+    path.push(CONF_FILE);
+    if path.exists()
     {
-        path.push(CONF_FILE);
-        if path.exists()
-        {
-            return Some(path);
-        }
+        return Some(path);
     }
     None
 }
@@ -53,15 +60,7 @@ fn loadConfig(specified: Option<&str>) -> Result<config::ConfigParams, Error>
 {
     let conf_file = if let Some(config) = specified
     {
-        if let Ok(path) = PathBuf::from_str(config)
-        {
-            path
-        }
-        else
-        {
-            log_error!("Invalid config path: {}", config);
-            exit(1);
-        }
+        PathBuf::from(config)
     }
     else if let Some(path) = findConfigFile()
     {
@@ -76,6 +75,79 @@ fn loadConfig(specified: Option<&str>) -> Result<config::ConfigParams, Error>
     readConfig(&conf_file)
 }
 
+/// The HTTP client to share for the whole run, so that connections
+/// are reused.
+fn httpClient(conf: &config::ConfigParams) -> Result<Arc<http::Client>, Error>
+{
+    Ok(Arc::new(http::Client::new(&conf.http)?))
+}
+
+fn parsePercent(p: &str) -> Result<u32, Error>
+{
+    p.trim_end_matches('%').parse().map_err(
+        |e| error_from!(InvalidInput, e, "Invalid percentage: {}", p))
+}
+
+/// Read an optional argument that can be cleared with “none”.
+/// Return None if the argument is not given, and Some(None) if it is
+/// “none”.
+fn clearableArg<T>(opts: &clap::ArgMatches, name: &str,
+                   parse: fn(&str) -> Result<T, Error>) ->
+    Result<Option<Option<T>>, Error>
+{
+    match opts.value_of(name)
+    {
+        None => Ok(None),
+        Some("none") => Ok(Some(None)),
+        Some(value) => Ok(Some(Some(parse(value)?))),
+    }
+}
+
+/// Read the alert settings from the command line, except the alert
+/// price, which can only be parsed in the currency of the item.
+fn alertSettings(opts: &clap::ArgMatches) -> Result<alert::Settings, Error>
+{
+    Ok(alert::Settings {
+        alert_price: None,
+        alert_drop_percent: opts.value_of("alert-drop").map(parsePercent)
+            .transpose()?,
+        alert_all_time_low: opts.is_present("alert-low"),
+        alert_back_in_stock: opts.is_present("alert-stock"),
+    })
+}
+
+/// Read a switch that can be turned on with `--<name>` and off with
+/// `--no-<name>`. Return None if neither is given.
+fn switchArg(opts: &clap::ArgMatches, name: &str) -> Option<bool>
+{
+    if opts.is_present(name)
+    {
+        Some(true)
+    }
+    else if opts.is_present(format!("no-{}", name))
+    {
+        Some(false)
+    }
+    else
+    {
+        None
+    }
+}
+
+/// Read the changes of `wishlist set` from the command line, except
+/// the alert price, like `alertSettings()`.
+fn itemUpdate(opts: &clap::ArgMatches) -> Result<data::ItemUpdate, Error>
+{
+    Ok(data::ItemUpdate {
+        name: opts.value_of("name").map(|s| s.to_owned()),
+        update_interval: clearableArg(opts, "interval", utils::parseDuration)?,
+        alert_price: None,
+        alert_drop_percent: clearableArg(opts, "alert-drop", parsePercent)?,
+        alert_all_time_low: switchArg(opts, "alert-low"),
+        alert_back_in_stock: switchArg(opts, "alert-stock"),
+    })
+}
+
 fn realMain() -> Result<(), Error>
 {
     let opts = clap::App::new("Wishlist service")
@@ -91,12 +163,68 @@ fn realMain() -> Result<(), Error>
                     .about("Start API server"))
         .subcommand(clap::App::new("add")
                     .about("Add an item")
+                    .arg(clap::Arg::with_name("store")
+                         .required(true)
+                         .help("Store name, or URL of the product page"))
+                    .arg(clap::Arg::with_name("id")
+                         .help("Item ID. Not needed if a URL is given"))
+                    .arg(clap::Arg::with_name("alert-price")
+                         .long("alert-price")
+                         .value_name("PRICE")
+                         .takes_value(true)
+                         .help("Alert when price drops to this or lower"))
+                    .arg(clap::Arg::with_name("alert-drop")
+                         .long("alert-drop")
+                         .value_name("PERCENT")
+                         .takes_value(true)
+                         .help("Alert when price drops by this percent at once"))
+                    .arg(clap::Arg::with_name("alert-low")
+                         .long("alert-low")
+                         .help("Alert when price is lower than ever before"))
+                    .arg(clap::Arg::with_name("alert-stock")
+                         .long("alert-stock")
+                         .help("Alert when the item can be ordered again")))
+        .subcommand(clap::App::new("set")
+                    .about("Change the settings of an item")
                     .arg(clap::Arg::with_name("store")
                          .required(true)
                          .help("Store name"))
                     .arg(clap::Arg::with_name("id")
                          .required(true)
-                         .help("Item ID")))
+                         .help("Item ID"))
+                    .arg(clap::Arg::with_name("name")
+                         .long("name")
+                         .takes_value(true)
+                         .help("Display name of the item"))
+                    .arg(clap::Arg::with_name("interval")
+                         .long("interval")
+                         .value_name("DURATION")
+                         .takes_value(true)
+                         .help("Time between price updates, e.g. 90m, 2h. “none” to use the default"))
+                    .arg(clap::Arg::with_name("alert-price")
+                         .long("alert-price")
+                         .value_name("PRICE")
+                         .takes_value(true)
+                         .help("Alert when price drops to this or lower. “none” to unset"))
+                    .arg(clap::Arg::with_name("alert-drop")
+                         .long("alert-drop")
+                         .value_name("PERCENT")
+                         .takes_value(true)
+                         .help("Alert when price drops by this percent at once. “none” to unset"))
+                    .arg(clap::Arg::with_name("alert-low")
+                         .long("alert-low")
+                         .conflicts_with("no-alert-low")
+                         .help("Alert when price is lower than ever before"))
+                    .arg(clap::Arg::with_name("no-alert-low")
+                         .long("no-alert-low")
+                         .help("Do not alert on all-time low"))
+                    .arg(clap::Arg::with_name("alert-stock")
+                         .long("alert-stock")
+                         .conflicts_with("no-alert-stock")
+                         .help("Alert when the item can be ordered again"))
+                    .arg(clap::Arg::with_name("no-alert-stock")
+                         .long("no-alert-stock")
+                         .help("Do not alert when back in stock")))
         .subcommand(clap::App::new("remove")
                     .about("Remove an item and its price history")
                     .arg(clap::Arg::with_name("store")
@@ -109,6 +237,12 @@ fn realMain() -> Result<(), Error>
                     .about("Print all items"))
         .subcommand(clap::App::new("update")
                     .about("Update price of all items"))
+        .subcommand(clap::App::new("compare")
+                    .about("Compare the price of a game in several stores or regions")
+                    .arg(clap::Arg::with_name("items")
+                         .required(true)
+                         .multiple(true)
+                         .help("<store>/<id>, or URL of the product page")))
         .get_matches();
 
     match opts.subcommand_name()
@@ -117,7 +251,7 @@ fn realMain() -> Result<(), Error>
         {
             let conf = loadConfig(opts.value_of("config"))?;
             middle::maybeInitDB(&conf)?;
-            let server = web::WebHandler::new(&conf);
+            let server = web::WebHandler::new(&conf, httpClient(&conf)?)?;
             info!("Wishlist service starting...");
             server.start();
         },
@@ -126,8 +260,27 @@ fn realMain() -> Result<(), Error>
         {
             let conf = loadConfig(opts.value_of("config"))?;
             let subopts = opts.subcommand_matches("add").unwrap();
-            middle::addItem(subopts.value_of("store").unwrap(),
-                            subopts.value_of("id").unwrap(), conf)?;
+            let store = subopts.value_of("store").unwrap();
+            let alert = alertSettings(subopts)?;
+            let alert_price = subopts.value_of("alert-price");
+            let http = httpClient(&conf)?;
+            match subopts.value_of("id")
+            {
+                Some(id) => middle::addItem(store, id, &alert, alert_price,
+                                            conf, http)?,
+                None => middle::addItemByURL(store, &alert, alert_price, conf,
+                                             http)?,
+            }
+        },
+        Some("set") =>
+        {
+            let subopts = opts.subcommand_matches("set").unwrap();
+            let conf = loadConfig(opts.value_of("config"))?;
+            let alert_price = clearableArg(subopts, "alert-price",
+                                           |p| Ok(p.to_owned()))?;
+            middle::updateItem(subopts.value_of("store").unwrap(),
+                               subopts.value_of("id").unwrap(),
+                               &itemUpdate(subopts)?, alert_price, conf)?;
         },
         Some("remove") =>
         {
@@ -144,7 +297,16 @@ fn realMain() -> Result<(), Error>
         Some("update") =>
         {
             let conf = loadConfig(opts.value_of("config"))?;
-            middle::updateItemPrices(conf)?;
+            let http = httpClient(&conf)?;
+            middle::updateItemPrices(conf, http)?;
+        },
+        Some("compare") =>
+        {
+            let subopts = opts.subcommand_matches("compare").unwrap();
+            let conf = loadConfig(opts.value_of("config"))?;
+            let items: Vec<&str> = subopts.values_of("items").unwrap().collect();
+            let http = httpClient(&conf)?;
+            middle::compareItems(&items, conf, http)?;
         },
         None =>
         {
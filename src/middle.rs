@@ -1,19 +1,30 @@
+use std::collections::HashMap;
 use std::path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
-use chrono::Utc;
-use log::info;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
 use log::error as log_error;
-use tokio::process::Command;
-use tokio::io::AsyncWriteExt;
-use std::process::Stdio;
 
 use crate::error::Error;
 use crate::config;
 use crate::store;
 use crate::data;
+use crate::limit;
+use crate::notify;
+use crate::alert;
+use crate::exchange;
+use crate::http;
+use crate::utils;
+use crate::utils::Money;
 
+const SCHEDULER_MIN_WAIT: Duration = Duration::from_secs(60);
+const SCHEDULER_MAX_WAIT: Duration = Duration::from_secs(600);
+
+/// Create the database if it does not exist, and bring its schema up
+/// to date.
 pub fn maybeInitDB(conf: &config::ConfigParams) -> Result<(), Error>
 {
     let db_path = path::Path::new(&conf.db_file);
@@ -21,98 +32,295 @@ pub fn maybeInitDB(conf: &config::ConfigParams) -> Result<(), Error>
     {
         info!("Database not found. Creating new database at {}...",
               conf.db_file);
-        let mut d = data::DataManager::new(data::SqliteFilename::File(
-            PathBuf::from(db_path)));
-        d.connect()?;
-        d.init()
-    }
-    else
-    {
-        Ok(())
     }
+    let mut d = data::DataManager::new(data::SqliteFilename::File(
+        PathBuf::from(db_path)));
+    d.connect()
 }
 
-async fn updatePrice(item: store::ItemInfo) -> Result<store::ItemInfo, Error>
+async fn updatePrice(item: &store::ItemInfo, registry: &store::Registry,
+                     limiter: &limit::Limiter) ->
+    Result<store::ItemInfo, Error>
 {
-    let s = store::Store::new(&item.store)?;
+    let s = store::Store::new(&item.store, registry)?;
+    let _permit = limiter.acquire(&item.store, s.family()).await;
     s.get(&item.id).await
 }
 
-async fn alert(item: &store::ItemInfo, conf: &config::ConfigParams) ->
-    Result<(), Error>
+/// Result of querying the store for an item.
+struct PriceCheck
 {
-    if conf.telegram_notifier.is_none() { return Ok(()); }
-
-    let msg = format!("Price of [{}]({}) is now at {}!",
-                      item.name, item.url, item.price_str);
-    let mut child = Command::new(conf.telegram_notifier.clone().unwrap())
-        .stdin(Stdio::piped()).spawn().map_err(
-            |_| rterr!("Failed to spawn telegram-notify-bot"))?;
-    let child_stdin = child.stdin.as_mut().unwrap();
-    child_stdin.write_all((&msg).as_bytes()).await.map_err(
-        |_| rterr!("Failed to write message to telegram-notify-bot"))?;
-    child.wait().await.map_err(|_| rterr!("telegram-notify-bot failed"))?;
-    Ok(())
+    key: data::ItemKey,
+    /// The item with its new price, if the price or the availability
+    /// changed.
+    changed: Option<store::ItemInfo>,
+    /// Why the query failed, if it did.
+    error: Option<Error>,
+    /// Whether an alert was sent.
+    alerted: bool,
 }
 
-async fn updatePrices(items: Vec<store::ItemInfo>, conf: config::ConfigParams) ->
-    Result<Vec<store::ItemInfo>, Error>
+/// Query the store for the price of an item, and alert if the price
+/// drop or the return to stock meets the alert settings of the item,
+/// and is not a repeat of the last alert.
+async fn checkPrice(item: store::ItemInfo, history: alert::History,
+                    registry: &store::Registry, limiter: &limit::Limiter,
+                    notifiers: &notify::Notifiers,
+                    conf: &config::ConfigParams) -> PriceCheck
+{
+    let key = data::ItemKey::fromItem(&item);
+    let mut item_with_new_price = match updatePrice(&item, registry, limiter).await
+    {
+        Ok(p) => p,
+        Err(e) =>
+        {
+            log_error!("Failed to update price for {}: {}", item.name, e);
+            return PriceCheck { key, changed: None, error: Some(e),
+                                alerted: false };
+        }
+    };
+    if item_with_new_price.availability.cannotOrder() &&
+        item_with_new_price.price == 0
+    {
+        // The store does not show the price. Keep the last known one.
+        item_with_new_price.price = item.price;
+        item_with_new_price.price_str = item.price_str.clone();
+        item_with_new_price.currency = item.currency.clone();
+    }
+    if item_with_new_price.price == item.price &&
+        item_with_new_price.availability == item.availability
+    {
+        return PriceCheck { key, changed: None, error: None, alerted: false };
+    }
+    if item_with_new_price.availability != item.availability
+    {
+        info!("{} is now {}.", item_with_new_price.name,
+              item_with_new_price.availability.code());
+    }
+    if item_with_new_price.price != item.price
+    {
+        info!("Price of {} changed to {}.",
+              item_with_new_price.name, item_with_new_price.price_str);
+    }
+    let reason = alert::shouldAlert(
+        &item, &item_with_new_price, &history,
+        Duration::from_secs(conf.alert_cooldown_sec), Utc::now());
+    // Failures are logged by the notifiers. An alert that reached no
+    // channel is not recorded, so that it is sent again next time. One
+    // that reached some of them is recorded, or the working channels
+    // would get it again and again.
+    let alerted = match reason
+    {
+        Some(reason) => notifiers.notify(&notify::Message::priceAlert(
+            &item_with_new_price, &reason)).await.delivered(),
+        None => false,
+    };
+    PriceCheck { key, changed: Some(item_with_new_price), error: None, alerted }
+}
+
+/// Query the stores for the items that are due for a price update,
+/// and alert on price drops. `histories` has the alert history of
+/// the items. The queries run concurrently within the limits in the
+/// config.
+async fn updatePrices(items: Vec<store::ItemInfo>,
+                      mut histories: HashMap<data::ItemKey, alert::History>,
+                      conf: &config::ConfigParams, registry: &store::Registry,
+                      notifiers: &notify::Notifiers) -> Vec<PriceCheck>
 {
     let now = Utc::now();
-    let mut result: Vec<store::ItemInfo> = Vec::new();
     let default_interval = Duration::new(conf.update_interval_sec, 0);
-    for item in items
-    {
-        let interval = chrono::Duration::from_std(
-            item.update_interval.or(Some(default_interval)).unwrap()).map_err(
-            |_| rterr!("Failed to convert interval"))?;
-        // Allow 1 minute fluctuation
-        if item.last_update + interval < now + chrono::Duration::minutes(1)
+    let limiter = limit::Limiter::new(conf);
+    // Allow 1 minute fluctuation
+    let checks = items.into_iter().filter(
+        |item| item.nextUpdate(default_interval) <
+            now + chrono::Duration::minutes(1))
+        .map(|item| {
+            let history = histories.remove(&data::ItemKey::fromItem(&item))
+                .unwrap_or_default();
+            checkPrice(item, history, registry, &limiter, notifiers, conf)
+        });
+    futures::future::join_all(checks).await
+}
+
+/// The lowest price in `history` at which the item could be ordered
+/// in `currency`. Prices of 0 are left out, as they mean the store did
+/// not show one.
+fn lowestPrice(history: &[data::PricePoint], currency: &str) -> Option<i64>
+{
+    history.iter().filter(|p| !p.availability.cannotOrder() && p.price > 0 &&
+                          p.currency == currency)
+        .map(|p| p.price as i64).min()
+}
+
+/// Update the prices of all items that are due, and save them in
+/// the database.
+async fn updateAndSavePrices(conf: &config::ConfigParams,
+                             registry: &store::Registry,
+                             notifiers: &notify::Notifiers) -> Result<(), Error>
+{
+    // Do not keep the database open while querying the stores.
+    let (items, histories) = {
+        let mut d = data::DataManager::newWithFilename(&conf.db_file);
+        d.connect()?;
+        let items = d.getItems()?;
+        let mut histories = HashMap::new();
+        for item in &items
         {
-            let orig_price = item.price;
-            let name = item.name.clone();
-            let item_with_new_price = match updatePrice(item).await
+            let key = data::ItemKey::fromItem(item);
+            let lowest = if item.alert_all_time_low
             {
-                Ok(p) => p,
-                Err(e) =>
-                {
-                    log_error!("Failed to update price for {}: {}", name, e);
-                    continue;
-                }
-            };
-            if item_with_new_price.price == orig_price
-            {
-                continue;
+                lowestPrice(&d.priceHistory(key.clone())?, &item.currency)
             }
-            info!("Price of {} changed to {}.",
-                  item_with_new_price.name, item_with_new_price.price_str);
-            if item_with_new_price.price < orig_price
+            else
             {
-                if let Err(err) = alert(&item_with_new_price, &conf).await
-                {
-                    log_error!("{}", err);
-                }
-            }
-            result.push(item_with_new_price);
+                None
+            };
+            let last_alert = d.lastAlert(key.clone())?;
+            histories.insert(key, alert::History { lowest, last_alert });
         }
-    }
-    Ok(result)
+        (items, histories)
+    };
+    let checks = updatePrices(items, histories, conf, registry, notifiers).await;
+    let mut d = data::DataManager::newWithFilename(&conf.db_file);
+    d.connect()?;
+    savePrices(&d, checks, Utc::now());
+    Ok(())
 }
 
-pub fn addItem(store: &str, id: &str, conf: config::ConfigParams) ->
+/// Save the result of a price check at `now`.
+fn saveCheck(d: &data::DataManager, check: PriceCheck, now: DateTime<Utc>) ->
     Result<(), Error>
 {
-    maybeInitDB(&conf)?;
-    // Try to get price
-    let rt = tokio::runtime::Runtime::new().map_err(
-        |_| rterr!("Failed to create runtime"))?;
-    let key = data::ItemKey{store: store.to_owned(), id: id.to_owned()};
-    let item = rt.block_on(store::Store::new(&key.store)?.get(&key.id))?;
-    info!("Adding {} at {}...", item.name, item.price_str);
+    if let Some(item) = check.changed
+    {
+        d.addPrice(&item)?;
+        if check.alerted
+        {
+            d.recordAlert(check.key.clone(), item.price, now)?;
+        }
+    }
+    d.recordCheck(check.key, now, check.error.map(|e| e.to_string()).as_deref())
+}
+
+/// Save the results of the price checks at `now`. A check that cannot
+/// be saved, e.g. because the item was removed while the stores were
+/// queried, does not stop the others.
+fn savePrices(d: &data::DataManager, checks: Vec<PriceCheck>,
+              now: DateTime<Utc>)
+{
+    for check in checks
+    {
+        let (store, id) = (check.key.store.clone(), check.key.id.clone());
+        if let Err(e) = saveCheck(d, check, now)
+        {
+            warn!("Failed to save price check for {}/{}: {}", store, id, e);
+        }
+    }
+}
+
+/// When the next item is due for a price update.
+fn nextUpdate(conf: &config::ConfigParams) -> Result<Option<DateTime<Utc>>, Error>
+{
     let mut d = data::DataManager::newWithFilename(&conf.db_file);
     d.connect()?;
+    let default_interval = Duration::new(conf.update_interval_sec, 0);
+    Ok(d.getItems()?.iter().map(|item| item.nextUpdate(default_interval)).min())
+}
+
+/// Keep updating prices in the background, each item at its own
+/// interval. This never returns.
+pub async fn runScheduler(conf: config::ConfigParams,
+                          registry: Arc<store::Registry>,
+                          notifiers: Arc<notify::Notifiers>)
+{
+    info!("Price update scheduler started.");
+    loop
+    {
+        if let Err(e) = updateAndSavePrices(&conf, &registry, &notifiers).await
+        {
+            log_error!("Failed to update prices: {}", e);
+        }
+        let wait = match nextUpdate(&conf)
+        {
+            Ok(Some(t)) => (t - Utc::now()).to_std().unwrap_or_default(),
+            Ok(None) => SCHEDULER_MAX_WAIT,
+            Err(e) =>
+            {
+                log_error!("Failed to find next update time: {}", e);
+                SCHEDULER_MIN_WAIT
+            },
+        };
+        // Items added from the command line are not known until the
+        // next round, so do not sleep too long.
+        let wait = wait.clamp(SCHEDULER_MIN_WAIT, SCHEDULER_MAX_WAIT);
+        debug!("Next price update in {} seconds.", wait.as_secs());
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Query the store for an item, and add it to the database in
+/// `db_file` with the `alert` settings. `alert_price` is parsed in the
+/// currency of the item, and overrides the one in `alert`. Return the
+/// added item.
+pub async fn fetchAndAddItem(key: &data::ItemKey, alert: &alert::Settings,
+                             alert_price: Option<&str>, db_file: &str,
+                             registry: &store::Registry) ->
+    Result<store::ItemInfo, Error>
+{
+    alert.validate()?;
+    let mut item = store::Store::new(&key.store, registry)?.get(&key.id).await?;
+    let mut alert = alert.clone();
+    if let Some(p) = alert_price
+    {
+        alert.alert_price = Some(alert::parseAlertPrice(p, &item.currency)?);
+    }
+    alert.applyTo(&mut item);
+    info!("Adding {} at {}...", item.name, item.price_str);
+    let mut d = data::DataManager::newWithFilename(db_file);
+    d.connect()?;
     d.addItem(&item)?;
-    d.addPrice(&item)
+    d.addPrice(&item)?;
+    Ok(item)
+}
+
+/// Like `fetchAndAddItem()`, but by the URL of the product page.
+pub async fn fetchAndAddItemByURL(url: &str, alert: &alert::Settings,
+                                  alert_price: Option<&str>, db_file: &str,
+                                  registry: &store::Registry) ->
+    Result<store::ItemInfo, Error>
+{
+    let key = registry.parseURL(url).ok_or_else(
+        || error!(NotFound, "Unrecognized URL: {}", url))?;
+    info!("Found item {} in store {}.", key.id, key.store);
+    fetchAndAddItem(&key, alert, alert_price, db_file, registry).await
+}
+
+/// Add an item with the `alert` settings. `alert_price` is parsed in
+/// the currency of the item, and overrides the one in `alert`.
+pub fn addItem(store: &str, id: &str, alert: &alert::Settings,
+               alert_price: Option<&str>, conf: config::ConfigParams,
+               http: Arc<http::Client>) -> Result<(), Error>
+{
+    maybeInitDB(&conf)?;
+    let rt = tokio::runtime::Runtime::new().map_err(
+        |e| error_from!(RuntimeError, e, "Failed to create runtime"))?;
+    let key = data::ItemKey{store: store.to_owned(), id: id.to_owned()};
+    let registry = store::Registry::fromConfig(&conf, http)?;
+    rt.block_on(fetchAndAddItem(&key, alert, alert_price, &conf.db_file,
+                                &registry)).map(|_| ())
+}
+
+/// Add an item by the URL of its product page.
+pub fn addItemByURL(url: &str, alert: &alert::Settings,
+                    alert_price: Option<&str>, conf: config::ConfigParams,
+                    http: Arc<http::Client>) -> Result<(), Error>
+{
+    maybeInitDB(&conf)?;
+    let rt = tokio::runtime::Runtime::new().map_err(
+        |e| error_from!(RuntimeError, e, "Failed to create runtime"))?;
+    let registry = store::Registry::fromConfig(&conf, http)?;
+    rt.block_on(fetchAndAddItemByURL(url, alert, alert_price, &conf.db_file,
+                                     &registry)).map(|_| ())
 }
 
 pub fn removeItem(store: &str, id: &str, conf: config::ConfigParams) ->
@@ -125,6 +333,33 @@ pub fn removeItem(store: &str, id: &str, conf: config::ConfigParams) ->
     d.removeItem(key)
 }
 
+/// Change the settings of an item. `alert_price` is like the one in
+/// `update`, but as given by the user, and is parsed in the currency
+/// of the item.
+pub fn updateItem(store: &str, id: &str, update: &data::ItemUpdate,
+                  alert_price: Option<Option<String>>,
+                  conf: config::ConfigParams) -> Result<(), Error>
+{
+    let key = data::ItemKey{store: store.to_owned(), id: id.to_owned()};
+    let mut d = data::DataManager::newWithFilename(&conf.db_file);
+    d.connect()?;
+    let mut update = update.clone();
+    match alert_price
+    {
+        None => {},
+        Some(None) => update.alert_price = Some(None),
+        Some(Some(p)) =>
+        {
+            let item = d.getItems()?.into_iter()
+                .find(|i| i.store == key.store && i.id == key.id)
+                .ok_or_else(|| error!(NotFound, "Item not found"))?;
+            update.alert_price = Some(Some(
+                alert::parseAlertPrice(&p, &item.currency)?));
+        },
+    }
+    d.updateItem(key, &update)
+}
+
 pub fn listItems(conf: config::ConfigParams) -> Result<(), Error>
 {
     let mut d = data::DataManager::newWithFilename(&conf.db_file);
@@ -134,21 +369,302 @@ pub fn listItems(conf: config::ConfigParams) -> Result<(), Error>
     {
         println!("{} {} {} {}", item.store, item.id, item.name,
                  item.price_str);
+        if item.availability != store::Availability::Unknown
+        {
+            println!("    {}", item.availability.code());
+        }
+        if let Some(dt) = item.update_interval
+        {
+            println!("    Update every {} seconds", dt.as_secs());
+        }
+        if let Some(p) = item.alert_price
+        {
+            println!("    Alert at {}",
+                     utils::formatPrice(p as i64, &item.currency));
+        }
+        if let Some(p) = item.alert_drop_percent
+        {
+            println!("    Alert on {}% drop", p);
+        }
+        if item.alert_all_time_low
+        {
+            println!("    Alert on all-time low");
+        }
+        if item.alert_back_in_stock
+        {
+            println!("    Alert when back in stock");
+        }
+        if item.failure_count > 0
+        {
+            println!("    Failed {} times in a row: {}", item.failure_count,
+                     item.last_error.unwrap_or_default());
+        }
     }
     Ok(())
 }
 
-pub fn updateItemPrices(conf: config::ConfigParams) -> Result<(), Error>
+/// The price of an item in one of the compared stores.
+pub struct Comparison
 {
-    let mut d = data::DataManager::newWithFilename(&conf.db_file);
-    d.connect()?;
+    pub item: store::ItemInfo,
+    /// Price * 100 in the home currency. None if there is no exchange
+    /// rate for the currency of the item.
+    pub home_price: Option<i64>,
+}
+
+/// Query the stores for the same game in several stores or regions,
+/// where it usually has a different ID in each. Return the results
+/// from the cheapest in `home_currency`, followed by those that cannot
+/// be converted, and the queries that failed.
+pub async fn comparePrices(keys: &[data::ItemKey], registry: &store::Registry,
+                           limiter: &limit::Limiter, rates: &exchange::Rates,
+                           home_currency: &str) ->
+    (Vec<Comparison>, Vec<(data::ItemKey, Error)>)
+{
+    let queries = keys.iter().map(|key| async move {
+        let item = store::ItemInfo::new(&key.store, &key.id);
+        (key, updatePrice(&item, registry, limiter).await)
+    });
+    let mut found = Vec::new();
+    let mut failed = Vec::new();
+    for (key, result) in futures::future::join_all(queries).await
+    {
+        match result
+        {
+            Ok(item) =>
+            {
+                let home_price = rates.convert(item.price, &item.currency,
+                                               home_currency).ok();
+                found.push(Comparison { item, home_price });
+            },
+            Err(e) => failed.push((key.clone(), e)),
+        }
+    }
+    found.sort_by_key(|c| (c.home_price.is_none(), c.home_price));
+    (found, failed)
+}
+
+/// Print the prices of the same game in several stores, cheapest
+/// first. Each of `items` is either “<store>/<id>” or the URL of the
+/// product page.
+pub fn compareItems(items: &[&str], conf: config::ConfigParams,
+                    http: Arc<http::Client>) -> Result<(), Error>
+{
+    let registry = store::Registry::fromConfig(&conf, http)?;
+    let keys = items.iter().map(|s| {
+        registry.parseURL(s).or_else(|| s.split_once('/').map(
+            |(store, id)| data::ItemKey { store: store.to_owned(),
+                                          id: id.to_owned() }))
+            .ok_or_else(|| error!(InvalidInput, "Not <store>/<id> or a known \
+                                                 URL: {}", s))
+    }).collect::<Result<Vec<_>, Error>>()?;
+    let limiter = limit::Limiter::new(&conf);
+    let rates = exchange::fromConfig(&conf, registry.http());
     let rt = tokio::runtime::Runtime::new().map_err(
-        |_| rterr!("Failed to create runtime"))?;
-    info!("Updating prices...");
-    let items = rt.block_on(updatePrices(d.getItems()?, conf))?;
-    for item in items
+        |e| error_from!(RuntimeError, e, "Failed to create runtime"))?;
+    let (found, failed) = rt.block_on(async {
+        let rates = rates.rates().await?;
+        Ok::<_, Error>(comparePrices(&keys, &registry, &limiter, &rates,
+                                     &conf.home_currency).await)
+    })?;
+    for c in found
     {
-        d.addPrice(&item)?;
+        let home = match c.home_price
+        {
+            Some(p) if c.item.currency != conf.home_currency =>
+                format!(" (≈ {})", Money::new(p, &conf.home_currency)),
+            Some(_) => String::new(),
+            None => String::from(" (no exchange rate)"),
+        };
+        println!("{} {} {} {}{}", c.item.store, c.item.id, c.item.name,
+                 c.item.price_str, home);
+    }
+    for (key, e) in failed
+    {
+        log_error!("Failed to get price from {} for {}: {}", key.store, key.id,
+                   e);
     }
     Ok(())
 }
+
+pub fn updateItemPrices(conf: config::ConfigParams, http: Arc<http::Client>) ->
+    Result<(), Error>
+{
+    let registry = store::Registry::fromConfig(&conf, http.clone())?;
+    let notifiers = notify::Notifiers::fromConfig(&conf, http)?;
+    let rt = tokio::runtime::Runtime::new().map_err(
+        |e| error_from!(RuntimeError, e, "Failed to create runtime"))?;
+    info!("Updating prices...");
+    rt.block_on(updateAndSavePrices(&conf, &registry, &notifiers))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use async_trait::async_trait;
+    use crate::testing::MockServer;
+
+    /// Sells every item at a fixed price per region.
+    struct Dummy;
+
+    #[async_trait]
+    impl store::StoreBackend for Dummy
+    {
+        fn name(&self) -> &str { "dummy" }
+
+        fn regions(&self) -> Vec<String>
+        {
+            vec![String::from("us"), String::from("jp"), String::from("uk")]
+        }
+
+        async fn get(&self, _http: &http::StoreClient, region: Option<&str>,
+                     id: &str) -> Result<store::ItemInfo, Error>
+        {
+            let price = match region
+            {
+                Some("us") => Money::new(2000, "USD"),
+                Some("jp") => Money::new(150000, "JPY"),
+                _ => Money::new(1000, "GBP"),
+            };
+            let mut item = store::ItemInfo::new(
+                &store::storeName("dummy", region), id);
+            item.setPrice(&price);
+            Ok(item)
+        }
+    }
+
+    fn key(store: &str, id: &str) -> data::ItemKey
+    {
+        data::ItemKey { store: store.to_owned(), id: id.to_owned() }
+    }
+
+    #[test]
+    fn compare_prices() -> Result<(), Error>
+    {
+        let conf = config::ConfigParams::default();
+        let mut registry = store::Registry::empty(Arc::new(
+            http::Client::new(&conf.http)?));
+        registry.register(Arc::new(Dummy))?;
+        let limiter = limit::Limiter::new(&conf);
+        let mut rates = exchange::Rates::empty("USD");
+        rates.rates.insert(String::from("JPY"), 150.0);
+
+        let keys = [key("dummy-us", "a"), key("dummy-uk", "b"),
+                    key("dummy-jp", "c"), key("nowhere", "d")];
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (found, failed) = rt.block_on(comparePrices(
+            &keys, &registry, &limiter, &rates, "USD"));
+        let order: Vec<(&str, Option<i64>)> = found.iter().map(
+            |c| (c.item.store.as_str(), c.home_price)).collect();
+        assert_eq!(order, vec![("dummy-jp", Some(1000)),
+                               ("dummy-us", Some(2000)),
+                               ("dummy-uk", None)]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0.store, "nowhere");
+        Ok(())
+    }
+
+    #[test]
+    fn lowest_price() -> Result<(), Error>
+    {
+        let point = |price: u64, currency: &str, availability| data::PricePoint {
+            time: Utc::now(), price, currency: currency.to_owned(),
+            availability };
+        let history = vec![
+            point(2500, "USD", store::Availability::InStock),
+            point(0, "USD", store::Availability::OutOfStock),
+            point(1800, "USD", store::Availability::OutOfStock),
+            point(1000, "EUR", store::Availability::InStock),
+            point(2200, "USD", store::Availability::InStock)];
+        let lowest = lowestPrice(&history, "USD");
+        assert_eq!(lowest, Some(2200));
+        assert_eq!(lowestPrice(&history[1..2], "USD"), None);
+
+        // A new low alerts in spite of the out-of-stock 0.
+        let mut item = store::ItemInfo::new("dummy-us", "a");
+        item.setPrice(&Money::new(2500, "USD"));
+        item.alert_all_time_low = true;
+        assert_eq!(alert::alertReason(&item, 2100, lowest),
+                   Some(alert::Reason::AllTimeLow));
+        Ok(())
+    }
+
+    #[test]
+    fn save_prices_of_removed_item() -> Result<(), Error>
+    {
+        let mut d = data::DataManager::new(data::SqliteFilename::InMemory);
+        d.connect()?;
+        let mut items = Vec::new();
+        for id in ["a", "b"]
+        {
+            let mut item = store::ItemInfo::new("dummy-us", id);
+            item.setPrice(&Money::new(2500, "USD"));
+            d.addItem(&item)?;
+            d.addPrice(&item)?;
+            item.setPrice(&Money::new(2000, "USD"));
+            items.push(item);
+        }
+        let checks = items.into_iter().map(|item| PriceCheck {
+            key: data::ItemKey::fromItem(&item), changed: Some(item),
+            error: None, alerted: true }).collect();
+        // “a” is removed while the stores are queried.
+        d.removeItem(key("dummy-us", "a"))?;
+        let now = Utc::now();
+        savePrices(&d, checks, now);
+
+        let items = d.getItems()?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, "b");
+        let prices: Vec<u64> = d.priceHistory(key("dummy-us", "b"))?.iter()
+            .map(|p| p.price).collect();
+        assert!(prices.len() == 2 && prices.contains(&2000));
+        assert_eq!(items[0].last_check.timestamp(), now.timestamp());
+        let last_alert = d.lastAlert(key("dummy-us", "b"))?.unwrap();
+        assert_eq!(last_alert.price, 2000);
+        Ok(())
+    }
+
+    #[test]
+    fn alert_once_with_failed_channel() -> Result<(), Error>
+    {
+        let mut conf = config::ConfigParams::default();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // Only “/hook” works; the other channel gets 404.
+            let server = MockServer::start(vec![("/hook", String::new())]);
+            conf.notifier = vec![
+                config::NotifierParams::Webhook {
+                    url: server.url("/broken"), headers: HashMap::new() },
+                config::NotifierParams::Webhook {
+                    url: server.url("/hook"), headers: HashMap::new() }];
+            let http = Arc::new(http::Client::new(&conf.http)?);
+            let notifiers = notify::Notifiers::fromConfig(&conf, http.clone())?;
+            let mut registry = store::Registry::empty(http);
+            registry.register(Arc::new(Dummy))?;
+            let limiter = limit::Limiter::new(&conf);
+
+            let mut item = store::ItemInfo::new("dummy-us", "a");
+            item.setPrice(&Money::new(2500, "USD"));
+            let check = checkPrice(item.clone(), alert::History::default(),
+                                   &registry, &limiter, &notifiers, &conf).await;
+            assert!(check.alerted);
+            let hooks = || server.requests().iter().filter(|r| *r == "/hook")
+                .count();
+            assert_eq!(hooks(), 1);
+
+            // The price bounces back up and drops again. The alert
+            // above was recorded, so it is not sent again.
+            let history = alert::History {
+                lowest: None,
+                last_alert: Some(alert::LastAlert { price: 2000,
+                                                    time: Utc::now() }),
+            };
+            let check = checkPrice(item, history, &registry, &limiter,
+                                   &notifiers, &conf).await;
+            assert!(!check.alerted);
+            assert_eq!(hooks(), 1);
+            Ok(())
+        })
+    }
+}
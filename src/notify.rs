@@ -0,0 +1,745 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::AsyncTransport;
+use lettre::transport::smtp::authentication::Credentials;
+use log::error as log_error;
+use serde::Serialize;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::error::Error;
+use crate::config::{ConfigParams, NotifierParams, SmtpParams, SmtpSecurity};
+use crate::http;
+use crate::store::ItemInfo;
+use crate::utils;
+use crate::alert;
+
+type SmtpTransport = lettre::AsyncSmtpTransport<lettre::Tokio1Executor>;
+
+/// A notification about an item.
+#[derive(Serialize, Clone)]
+pub struct Message
+{
+    pub title: String,
+    /// Plain text.
+    pub body: String,
+    /// Same as `body`, but in Markdown with a link to the item.
+    pub markdown: String,
+    pub item: ItemInfo,
+}
+
+impl Message
+{
+    /// `item` has the new price. If it is on sale, the message says
+    /// so.
+    pub fn priceAlert(item: &ItemInfo, reason: &alert::Reason) -> Self
+    {
+        let (title, detail) = match reason
+        {
+            alert::Reason::Drop => ("Price drop", String::from("!")),
+            alert::Reason::BelowThreshold(p) =>
+                ("Below alert price",
+                 format!(", below your alert price {}.",
+                         utils::formatPrice(*p as i64, &item.currency))),
+            alert::Reason::PercentDrop(p) =>
+                ("Price drop", format!(", down {}%!", p)),
+            alert::Reason::AllTimeLow =>
+                ("All-time low", String::from(", the lowest ever!")),
+            alert::Reason::BackInStock =>
+            {
+                return Self {
+                    title: format!("Back in stock: {}", item.name),
+                    body: format!("{} can be ordered again at {}.", item.name,
+                                  item.price_str),
+                    markdown: format!("[{}]({}) can be ordered again at {}.",
+                                      item.name, item.url, item.price_str),
+                    item: item.clone(),
+                };
+            },
+        };
+        let sale = match (item.discount_percent, item.sale_end)
+        {
+            (None, _) => String::new(),
+            (Some(p), None) => format!(" On sale, {}% off.", p),
+            (Some(p), Some(end)) => format!(" On sale, {}% off until {}.", p,
+                                            end.format("%Y-%m-%d")),
+        };
+        let title = if title == "Price drop" && !sale.is_empty()
+        {
+            "Sale"
+        }
+        else
+        {
+            title
+        };
+        Self {
+            title: format!("{}: {}", title, item.name),
+            body: format!("Price of {} is now at {}{}{}", item.name,
+                          item.price_str, detail, sale),
+            markdown: format!("Price of [{}]({}) is now at {}{}{}", item.name,
+                              item.url, item.price_str, detail, sale),
+            item: item.clone(),
+        }
+    }
+}
+
+/// A channel to send notifications to.
+#[async_trait]
+pub trait Notifier: Send + Sync
+{
+    /// A short description of the channel, used in logs.
+    fn name(&self) -> String;
+    async fn notify(&self, msg: &Message) -> Result<(), Error>;
+}
+
+/// POST the message as JSON to a URL.
+struct Webhook
+{
+    url: String,
+    http: Arc<http::Client>,
+    headers: HashMap<String, String>,
+}
+
+#[async_trait]
+impl Notifier for Webhook
+{
+    fn name(&self) -> String { format!("webhook {}", self.url) }
+
+    async fn notify(&self, msg: &Message) -> Result<(), Error>
+    {
+        let mut req = self.http.post(&self.url).json(msg);
+        for (key, value) in &self.headers
+        {
+            req = req.header(key, value);
+        }
+        utils::postRequest(req).await.map(|_| ())
+    }
+}
+
+/// Publish to an ntfy topic (https://ntfy.sh). `url` includes the
+/// topic.
+struct Ntfy
+{
+    url: String,
+    http: Arc<http::Client>,
+    token: Option<String>,
+    priority: Option<u8>,
+}
+
+#[async_trait]
+impl Notifier for Ntfy
+{
+    fn name(&self) -> String { format!("ntfy {}", self.url) }
+
+    async fn notify(&self, msg: &Message) -> Result<(), Error>
+    {
+        let mut req = self.http.post(&self.url)
+            .header("Title", &msg.title).header("Click", &msg.item.url)
+            .body(msg.body.clone());
+        if let Some(p) = self.priority
+        {
+            req = req.header("Priority", p.to_string());
+        }
+        if let Some(token) = &self.token
+        {
+            req = req.bearer_auth(token);
+        }
+        utils::postRequest(req).await.map(|_| ())
+    }
+}
+
+/// Push to a Gotify server. `url` is the root of the server.
+struct Gotify
+{
+    url: String,
+    http: Arc<http::Client>,
+    token: String,
+    priority: Option<u8>,
+}
+
+#[async_trait]
+impl Notifier for Gotify
+{
+    fn name(&self) -> String { format!("gotify {}", self.url) }
+
+    async fn notify(&self, msg: &Message) -> Result<(), Error>
+    {
+        let url = format!("{}/message", self.url.trim_end_matches('/'));
+        let body = json!({
+            "title": msg.title,
+            "message": msg.markdown,
+            "priority": self.priority,
+            "extras": {
+                "client::display": { "contentType": "text/markdown" },
+                "client::notification": { "click": { "url": msg.item.url } },
+            },
+        });
+        let req = self.http.post(&url)
+            .header("X-Gotify-Key", &self.token).json(&body);
+        utils::postRequest(req).await.map(|_| ())
+    }
+}
+
+/// Send an email.
+struct Smtp
+{
+    host: String,
+    transport: SmtpTransport,
+    from: lettre::message::Mailbox,
+    to: Vec<lettre::message::Mailbox>,
+}
+
+impl Smtp
+{
+    fn new(params: &SmtpParams) -> Result<Self, Error>
+    {
+        let mut builder = match params.security
+        {
+            SmtpSecurity::None =>
+                SmtpTransport::builder_dangerous(&params.host),
+            SmtpSecurity::StartTls =>
+                SmtpTransport::starttls_relay(&params.host).map_err(
+                    |e| error_from!(Config, e, "Invalid SMTP server {}",
+                                    params.host))?,
+            SmtpSecurity::Tls =>
+                SmtpTransport::relay(&params.host).map_err(
+                    |e| error_from!(Config, e, "Invalid SMTP server {}",
+                                    params.host))?,
+        };
+        if let Some(port) = params.port
+        {
+            builder = builder.port(port);
+        }
+        match (&params.username, &params.password)
+        {
+            (Some(user), Some(password)) =>
+            {
+                builder = builder.credentials(
+                    Credentials::new(user.clone(), password.clone()));
+            },
+            (None, None) => {},
+            _ => return Err(error!(
+                Config, "SMTP username and password must be set together")),
+        }
+
+        let parseAddress = |addr: &str| addr.parse().map_err(
+            |e| error_from!(Config, e, "Invalid email address {}", addr));
+        if params.to.is_empty()
+        {
+            return Err(error!(Config, "No recipient for SMTP notifier"));
+        }
+        Ok(Self {
+            host: params.host.clone(),
+            transport: builder.build(),
+            from: parseAddress(&params.from)?,
+            to: params.to.iter().map(|addr| parseAddress(addr))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Smtp
+{
+    fn name(&self) -> String { format!("smtp {}", self.host) }
+
+    async fn notify(&self, msg: &Message) -> Result<(), Error>
+    {
+        let mut builder = lettre::Message::builder().from(self.from.clone())
+            .subject(msg.title.clone());
+        for to in &self.to
+        {
+            builder = builder.to(to.clone());
+        }
+        let email = builder.body(format!("{}\n\n{}\n", msg.body, msg.item.url))
+            .map_err(|e| error_from!(RuntimeError, e, "Failed to build email"))?;
+        self.transport.send(email).await.map_err(
+            |e| error_from!(Network, e, "Failed to send email"))?;
+        Ok(())
+    }
+}
+
+/// Run `program` with `args`, and optionally write `input` to its
+/// stdin.
+async fn runProgram(program: &str, args: &[String], input: Option<&str>) ->
+    Result<(), Error>
+{
+    let mut child = Command::new(program).args(args).stdin(Stdio::piped())
+        .spawn().map_err(
+            |e| error_from!(RuntimeError, e, "Failed to spawn {}", program))?;
+    if let Some(input) = input
+    {
+        let mut child_stdin = child.stdin.take().unwrap();
+        child_stdin.write_all(input.as_bytes()).await.map_err(
+            |e| error_from!(RuntimeError, e,
+                            "Failed to write message to {}", program))?;
+    }
+    else
+    {
+        drop(child.stdin.take());
+    }
+    let status = child.wait().await.map_err(
+        |e| error_from!(RuntimeError, e, "{} failed", program))?;
+    if !status.success()
+    {
+        return Err(rterr!("{} failed with {}", program, status));
+    }
+    Ok(())
+}
+
+/// Desktop notification with notify-send.
+struct NotifySend
+{
+    urgency: Option<String>,
+}
+
+impl NotifySend
+{
+    fn args(&self, msg: &Message) -> Vec<String>
+    {
+        let mut args = Vec::new();
+        if let Some(urgency) = &self.urgency
+        {
+            args.push(format!("--urgency={}", urgency));
+        }
+        args.push(msg.title.clone());
+        args.push(msg.body.clone());
+        args
+    }
+}
+
+#[async_trait]
+impl Notifier for NotifySend
+{
+    fn name(&self) -> String { String::from("notify-send") }
+
+    async fn notify(&self, msg: &Message) -> Result<(), Error>
+    {
+        runProgram("notify-send", &self.args(msg), None).await
+    }
+}
+
+/// Run a program and write the message in Markdown to its stdin,
+/// e.g. the Telegram controller.
+struct Subprocess
+{
+    command: String,
+    args: Vec<String>,
+}
+
+#[async_trait]
+impl Notifier for Subprocess
+{
+    fn name(&self) -> String { format!("command {}", self.command) }
+
+    async fn notify(&self, msg: &Message) -> Result<(), Error>
+    {
+        runProgram(&self.command, &self.args, Some(&msg.markdown)).await
+    }
+}
+
+fn newNotifier(params: &NotifierParams, http: &Arc<http::Client>) ->
+    Result<Box<dyn Notifier>, Error>
+{
+    Ok(match params
+    {
+        NotifierParams::Webhook { url, headers } => Box::new(Webhook {
+            url: url.clone(),
+            http: http.clone(),
+            headers: headers.clone(),
+        }),
+        NotifierParams::Ntfy { url, token, priority } => Box::new(Ntfy {
+            url: url.clone(),
+            http: http.clone(),
+            token: token.clone(),
+            priority: *priority,
+        }),
+        NotifierParams::Gotify { url, token, priority } => Box::new(Gotify {
+            url: url.clone(),
+            http: http.clone(),
+            token: token.clone(),
+            priority: *priority,
+        }),
+        NotifierParams::Smtp(params) => Box::new(Smtp::new(params)?),
+        NotifierParams::NotifySend { urgency } => Box::new(NotifySend {
+            urgency: urgency.clone(),
+        }),
+        NotifierParams::Command { command, args } => Box::new(Subprocess {
+            command: command.clone(),
+            args: args.clone(),
+        }),
+    })
+}
+
+/// All the configured notification channels.
+pub struct Notifiers
+{
+    channels: Vec<Box<dyn Notifier>>,
+}
+
+impl Notifiers
+{
+    /// The channels in `[[notifier]]`, plus the Telegram controller
+    /// if `telegram_notifier` is set. HTTP channels use `http`.
+    pub fn fromConfig(conf: &ConfigParams, http: Arc<http::Client>) ->
+        Result<Self, Error>
+    {
+        let mut channels = conf.notifier.iter().map(|p| newNotifier(p, &http))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(path) = &conf.telegram_notifier
+        {
+            channels.push(Box::new(Subprocess {
+                command: path.clone(),
+                args: Vec::new(),
+            }));
+        }
+        Ok(Self { channels })
+    }
+
+    /// Send `msg` to all channels. A failed channel does not stop the
+    /// others.
+    pub async fn notify(&self, msg: &Message) -> Delivery
+    {
+        let results = futures::future::join_all(
+            self.channels.iter().map(|c| c.notify(msg))).await;
+        let mut delivery = Delivery { sent: 0, failed: Vec::new() };
+        for (channel, result) in self.channels.iter().zip(results)
+        {
+            match result
+            {
+                Ok(()) => delivery.sent += 1,
+                Err(e) =>
+                {
+                    log_error!("Failed to notify via {}: {}", channel.name(), e);
+                    delivery.failed.push((channel.name(), e));
+                },
+            }
+        }
+        delivery
+    }
+}
+
+/// The outcome of sending a message to each channel.
+pub struct Delivery
+{
+    /// Number of channels that got the message.
+    pub sent: usize,
+    /// The channels that failed, and why.
+    pub failed: Vec<(String, Error)>,
+}
+
+impl Delivery
+{
+    /// Whether the message reached at least one channel, or there is
+    /// no channel to send to.
+    pub fn delivered(&self) -> bool
+    {
+        self.sent > 0 || self.failed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use warp::Filter;
+
+    type AnyError = Box<dyn std::error::Error>;
+
+    /// A request received by the stub HTTP server.
+    #[derive(Clone)]
+    struct Request
+    {
+        path: String,
+        headers: warp::http::HeaderMap,
+        body: String,
+    }
+
+    /// Serve HTTP with `status`. Return the URL, and the requests
+    /// received so far.
+    async fn serveHTTP(status: u16) -> (String, Arc<Mutex<Vec<Request>>>)
+    {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        let route = warp::path::full().and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(move |path: warp::path::FullPath, headers,
+                       body: warp::hyper::body::Bytes| {
+                received.lock().unwrap().push(Request {
+                    path: path.as_str().to_owned(),
+                    headers,
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                });
+                warp::reply::with_status(
+                    "", warp::http::StatusCode::from_u16(status).unwrap())
+            });
+        let (addr, server) = warp::serve(route)
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}", addr), requests)
+    }
+
+    /// Speak just enough SMTP to accept one email. Return the port,
+    /// and the received DATA when done.
+    async fn serveSMTP() -> (u16, tokio::task::JoinHandle<String>)
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 stub ESMTP\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap()
+            {
+                if in_data
+                {
+                    if line == "."
+                    {
+                        in_data = false;
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                    }
+                    else
+                    {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match &line.to_uppercase()[..4.min(line.len())]
+                {
+                    "EHLO" => b"250 stub\r\n",
+                    "DATA" => { in_data = true; b"354 Go ahead\r\n" },
+                    "QUIT" =>
+                    {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    },
+                    _ => b"250 OK\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    fn message() -> Message
+    {
+        let mut item = ItemInfo::new("ps4-us", "aaa");
+        item.name = String::from("Some game");
+        item.url = String::from("https://example.com/aaa");
+        item.price = 999;
+        item.price_str = String::from("$9.99");
+        item.currency = String::from("USD");
+        Message::priceAlert(&item, &alert::Reason::Drop)
+    }
+
+    fn notifiers(params: Vec<NotifierParams>) -> Result<Notifiers, Error>
+    {
+        let mut conf = ConfigParams::default();
+        conf.notifier = params;
+        Notifiers::fromConfig(&conf, Arc::new(http::Client::new(&conf.http)?))
+    }
+
+    #[test]
+    fn webhook() -> Result<(), AnyError>
+    {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let (url, requests) = serveHTTP(200).await;
+            let mut headers = HashMap::new();
+            headers.insert(String::from("X-Token"), String::from("secret"));
+            let n = notifiers(vec![NotifierParams::Webhook {
+                url: format!("{}/hook", url), headers,
+            }])?;
+            assert!(n.notify(&message()).await.failed.is_empty());
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].path, "/hook");
+            assert_eq!(requests[0].headers["X-Token"], "secret");
+            // Sent with the configured HTTP client
+            assert_eq!(requests[0].headers["User-Agent"],
+                       ConfigParams::default().http.user_agents[0].as_str());
+            let body: serde_json::Value = serde_json::from_str(&requests[0].body)?;
+            assert_eq!(body["title"], "Price drop: Some game");
+            assert_eq!(body["item"]["price"], 999);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn ntfy_and_gotify() -> Result<(), AnyError>
+    {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let (url, requests) = serveHTTP(200).await;
+            let n = notifiers(vec![
+                NotifierParams::Ntfy {
+                    url: format!("{}/deals", url), token: None,
+                    priority: Some(4),
+                },
+                NotifierParams::Gotify {
+                    url: format!("{}/", url), token: String::from("tok"),
+                    priority: None,
+                }])?;
+            assert!(n.notify(&message()).await.failed.is_empty());
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 2);
+            let ntfy = requests.iter().find(|r| r.path == "/deals").unwrap();
+            assert_eq!(ntfy.headers["Title"], "Price drop: Some game");
+            assert_eq!(ntfy.headers["Priority"], "4");
+            assert_eq!(ntfy.body, "Price of Some game is now at $9.99!");
+            let gotify = requests.iter().find(|r| r.path == "/message").unwrap();
+            assert_eq!(gotify.headers["X-Gotify-Key"], "tok");
+            let body: serde_json::Value = serde_json::from_str(&gotify.body)?;
+            assert_eq!(body["message"], "Price of [Some game](\
+                                         https://example.com/aaa) is now at \
+                                         $9.99!");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn smtp() -> Result<(), AnyError>
+    {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let (port, server) = serveSMTP().await;
+            let n = notifiers(vec![NotifierParams::Smtp(SmtpParams {
+                host: String::from("127.0.0.1"),
+                port: Some(port),
+                security: SmtpSecurity::None,
+                username: None,
+                password: None,
+                from: String::from("wishlist@example.com"),
+                to: vec![String::from("me@example.com")],
+            })])?;
+            assert!(n.notify(&message()).await.failed.is_empty());
+            let data = server.await?;
+            assert!(data.contains("Subject: Price drop: Some game"));
+            assert!(data.contains("To: me@example.com"));
+            assert!(data.contains("https://example.com/aaa"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn command() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let out = dir.path().join("msg");
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let n = notifiers(vec![NotifierParams::Command {
+                command: String::from("sh"),
+                args: vec![String::from("-c"),
+                           format!("cat > '{}'", out.to_string_lossy())],
+            }])?;
+            assert!(n.notify(&message()).await.failed.is_empty());
+            Ok::<_, Error>(())
+        })?;
+        assert_eq!(std::fs::read_to_string(&out)?,
+                   "Price of [Some game](https://example.com/aaa) is now at $9.99!");
+        Ok(())
+    }
+
+    #[test]
+    fn alert_message()
+    {
+        let mut item = message().item;
+        item.price_str = String::from("$4.99");
+        let msg = Message::priceAlert(&item,
+                                      &alert::Reason::BelowThreshold(500));
+        assert_eq!(msg.title, "Below alert price: Some game");
+        assert_eq!(msg.body, "Price of Some game is now at $4.99, below your \
+                              alert price $5.00.");
+        let mut yen = item.clone();
+        yen.price_str = String::from("¥1,500");
+        yen.currency = String::from("JPY");
+        let msg = Message::priceAlert(&yen,
+                                      &alert::Reason::BelowThreshold(198000));
+        assert_eq!(msg.body, "Price of Some game is now at ¥1,500, below your \
+                              alert price ¥1,980.");
+        let msg = Message::priceAlert(&item, &alert::Reason::PercentDrop(50));
+        assert_eq!(msg.markdown, "Price of [Some game](https://example.com/aaa) \
+                                  is now at $4.99, down 50%!");
+        item.discount_percent = Some(50);
+        item.sale_end = Some(utils::timestampToUtcTime(1715756399));
+        let msg = Message::priceAlert(&item, &alert::Reason::Drop);
+        assert_eq!(msg.title, "Sale: Some game");
+        assert_eq!(msg.body, "Price of Some game is now at $4.99! On sale, 50% \
+                              off until 2024-05-15.");
+        let msg = Message::priceAlert(&item, &alert::Reason::BackInStock);
+        assert_eq!(msg.title, "Back in stock: Some game");
+        assert_eq!(msg.body, "Some game can be ordered again at $4.99.");
+    }
+
+    #[test]
+    fn notify_send_args()
+    {
+        let n = NotifySend { urgency: Some(String::from("critical")) };
+        assert_eq!(n.args(&message()),
+                   vec!["--urgency=critical", "Price drop: Some game",
+                        "Price of Some game is now at $9.99!"]);
+    }
+
+    #[test]
+    fn failure_does_not_stop_others() -> Result<(), AnyError>
+    {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let (bad_url, _) = serveHTTP(403).await;
+            let (url, requests) = serveHTTP(200).await;
+            let n = notifiers(vec![
+                NotifierParams::Webhook { url: bad_url, headers: HashMap::new() },
+                NotifierParams::Webhook { url, headers: HashMap::new() }])?;
+            let delivery = n.notify(&message()).await;
+            assert!(delivery.delivered());
+            assert_eq!(delivery.sent, 1);
+            assert_eq!(delivery.failed.len(), 1);
+            assert_eq!(requests.lock().unwrap().len(), 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn no_repeat_on_server_error() -> Result<(), AnyError>
+    {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let (url, requests) = serveHTTP(500).await;
+            let n = notifiers(vec![
+                NotifierParams::Webhook { url, headers: HashMap::new() }])?;
+            let delivery = n.notify(&message()).await;
+            assert_eq!(delivery.failed.len(), 1);
+            assert_eq!(requests.lock().unwrap().len(), 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn invalid_smtp_config()
+    {
+        let params = SmtpParams {
+            host: String::from("127.0.0.1"),
+            port: None,
+            security: SmtpSecurity::None,
+            username: Some(String::from("me")),
+            password: None,
+            from: String::from("wishlist@example.com"),
+            to: vec![String::from("me@example.com")],
+        };
+        assert!(matches!(notifiers(vec![NotifierParams::Smtp(params)]),
+                         Err(Error::Config(..))));
+    }
+}
@@ -1,124 +1,274 @@
 use std::time::Duration;
 
+use async_trait::async_trait;
 use scraper::{Html, Selector};
 
+use crate::data::ItemKey;
 use crate::error::Error;
-use crate::store::ItemInfo;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend, storeName};
 use crate::utils;
 
-pub struct Amazon
+pub enum Region
 {
-    name: &'static str,
+    US,
+    UK,
+    DE,
+    JP,
+    CA,
 }
 
-impl Amazon
+impl Region
 {
-    pub fn new() -> Self
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
     {
-        Self{ name: "amazon-us" }
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            Some("uk") => Ok(Self::UK),
+            Some("de") => Ok(Self::DE),
+            Some("jp") => Ok(Self::JP),
+            Some("ca") => Ok(Self::CA),
+            _ => Err(error!(NotFound, "Invalid Amazon region: {:?}", code)),
+        }
     }
 
-    fn parsePriceTR(tr: scraper::element_ref::ElementRef) -> Option<&str>
+    /// The region of an Amazon domain, without “www.”.
+    fn fromDomain(domain: &str) -> Option<&'static str>
     {
-        if let Some(node_id) = tr.value().attr("id")
+        match domain
         {
-            if !node_id.starts_with("priceblock_")
-            {
-                return None;
-            }
-        }
-        else
-        {
-            return None;
+            "amazon.com" | "smile.amazon.com" => Some("us"),
+            "amazon.co.uk" => Some("uk"),
+            "amazon.de" => Some("de"),
+            "amazon.co.jp" => Some("jp"),
+            "amazon.ca" => Some("ca"),
+            _ => None,
         }
+    }
+}
+
+/// Containers of the price to pay, in the order of preference. The
+/// list price is also an “a-price”, but with “a-text-price”.
+const PRICE_SELECTORS: &[&str] = &[
+    "#corePriceDisplay_desktop_feature_div .a-price.priceToPay",
+    "#corePriceDisplay_desktop_feature_div .a-price:not(.a-text-price)",
+    "#corePrice_feature_div .a-price:not(.a-text-price)",
+    "#corePrice_desktop .a-price:not(.a-text-price)",
+    "#apex_desktop .a-price:not(.a-text-price)",
+];
+
+/// The price in the old layout, which some pages still use.
+const LEGACY_PRICE_SELECTOR: &str =
+    "#priceblock_ourprice, #priceblock_dealprice, #priceblock_saleprice";
+
+/// Lowercase text in “#availability” that means the item cannot be
+/// bought, in the languages of the supported regions.
+const UNAVAILABLE_TEXTS: &[&str] = &[
+    "currently unavailable",
+    "out of stock",
+    "derzeit nicht verfügbar",
+    "nicht auf lager",
+    "在庫切れ",
+    "お取り扱いできません",
+];
+
+/// Lowercase text in “#availability” that means the item can be
+/// pre-ordered.
+const PREORDER_TEXTS: &[&str] = &[
+    "pre-order",
+    "vorbestellbar",
+    "予約",
+];
+
+/// Lowercase text in “#availability” that means the item is in stock.
+/// Checked after `UNAVAILABLE_TEXTS`, which have some of these as
+/// parts.
+const IN_STOCK_TEXTS: &[&str] = &[
+    "in stock",
+    "auf lager",
+    "在庫あり",
+];
+
+fn text(node: scraper::ElementRef<'_>) -> String
+{
+    node.text().collect::<String>().trim().to_owned()
+}
+
+pub struct Amazon
+{
+    name: &'static str,
+    domain: &'static str,
+    currency: &'static str,
+}
 
-        // We have found a price tr.
-        let sel_td = Selector::parse("td").unwrap();
-        for td in tr.select(&sel_td)
+impl Amazon
+{
+    pub fn new(region: Region) -> Self
+    {
+        match region
         {
-            if let Some(price_s) = Self::parsePriceTD(td)
-            {
-                return Some(price_s);
-            }
+            Region::US => Self{ name: "amazon-us", domain: "amazon.com",
+                                currency: "USD" },
+            Region::UK => Self{ name: "amazon-uk", domain: "amazon.co.uk",
+                                currency: "GBP" },
+            Region::DE => Self{ name: "amazon-de", domain: "amazon.de",
+                                currency: "EUR" },
+            Region::JP => Self{ name: "amazon-jp", domain: "amazon.co.jp",
+                                currency: "JPY" },
+            Region::CA => Self{ name: "amazon-ca", domain: "amazon.ca",
+                                currency: "CAD" },
         }
-        None
     }
 
-    fn parsePriceTD(td: scraper::element_ref::ElementRef) -> Option<&str>
+    fn itemURL(&self, id: &str) -> String
     {
-        // The price span seems to be always in a td with class
-        // “a-span12”.
-        if let Some(c) = td.value().attr("class")
+        format!("https://www.{}/dp/{}", self.domain, id)
+    }
+
+    /// The text of an “a-price”. The whole text is usually in
+    /// “a-offscreen”, otherwise it is split into parts.
+    fn priceText(price: scraper::ElementRef<'_>) -> String
+    {
+        let sel_offscreen = Selector::parse(".a-offscreen").unwrap();
+        if let Some(s) = price.select(&sel_offscreen).map(text)
+            .find(|s| !s.is_empty())
         {
-            if !c.starts_with("a-span")
-            {
-                return None;
-            }
-        }
-        else
-        {
-            return None;
+            return s;
         }
+        let sel_parts = Selector::parse(
+            ".a-price-symbol, .a-price-whole, .a-price-fraction").unwrap();
+        price.select(&sel_parts).map(text).collect()
+    }
 
-        let sel_span = Selector::parse("span").unwrap();
-        for span in td.select(&sel_span)
+    /// The text of the price to pay, if there is one.
+    fn findPrice(document: &Html) -> Option<String>
+    {
+        for s in PRICE_SELECTORS
         {
-            if let Some(id) = span.value().attr("id")
+            let sel = Selector::parse(s).unwrap();
+            if let Some(price) = document.select(&sel).map(Self::priceText)
+                .find(|s| !s.is_empty())
             {
-                if id.starts_with("priceblock_")
-                {
-                    return span.text().next();
-                }
+                return Some(price);
             }
         }
-        None
+        let sel = Selector::parse(LEGACY_PRICE_SELECTOR).unwrap();
+        document.select(&sel).map(text).find(|s| !s.is_empty())
     }
 
-    pub async fn get(&self, id: &str) -> Result<ItemInfo, Error>
+    fn availability(document: &Html) -> Availability
     {
-        let url = format!("https://amazon.com/gp/product/{}/", id);
-        let content: String = utils::get(&url).await?;
-
-        let document = Html::parse_document(&content);
-
-        // Extract price
-        let sel_price = Selector::parse("#price tr").unwrap();
-        let mut price_str = String::new();
-        for node in document.select(&sel_price)
+        let sel_oos = Selector::parse("#outOfStock").unwrap();
+        if document.select(&sel_oos).next().is_some()
         {
-            if let Some(price_s) = Self::parsePriceTR(node)
-            {
-                price_str = price_s.to_owned();
-                break;
-            }
+            return Availability::OutOfStock;
+        }
+        let sel_avail = Selector::parse("#availability").unwrap();
+        let avail = match document.select(&sel_avail).next()
+        {
+            Some(node) => text(node).to_lowercase(),
+            None => return Availability::Unknown,
+        };
+        let has = |texts: &[&str]| texts.iter().any(|t| avail.contains(t));
+        if has(UNAVAILABLE_TEXTS)
+        {
+            Availability::OutOfStock
+        }
+        else if has(PREORDER_TEXTS)
+        {
+            Availability::Preorder
         }
-        if price_str.is_empty()
+        else if has(IN_STOCK_TEXTS)
         {
-            return Err(rterr!("Failed to get amazon price of {}", id));
+            Availability::InStock
         }
-        if !price_str.starts_with("$")
+        else
         {
-            return Err(rterr!("Invalid amazon price for {}: {}", id, price_str));
+            Availability::Unknown
         }
+    }
 
-        let price = utils::parsePrice(&price_str[1..])?;
+    /// Extract the item from its product page at `url`. An item that
+    /// is out of stock usually has no price, in which case the price
+    /// is 0.
+    fn parse(&self, id: &str, url: String, content: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let document = Html::parse_document(content);
+        let availability = Self::availability(&document);
+        let price_str = Self::findPrice(&document);
+        if price_str.is_none() && !availability.cannotOrder()
+        {
+            return Err(error!(StoreChanged, "Failed to get amazon price of {}",
+                              id));
+        }
 
-        // Extract name
         let sel_name = Selector::parse("#productTitle").unwrap();
-        let name_node = document.select(&sel_name).next().ok_or_else(
-            || rterr!("Failed to extract amazon name of {}", id))?;
-        let name = name_node.text().next().ok_or_else(
-            || rterr!("Amazon name node does not contain a name for product {}",
-                      id))?;
+        let name = document.select(&sel_name).next().map(text)
+            .filter(|s| !s.is_empty()).ok_or_else(
+                || error!(StoreChanged, "Failed to extract amazon name of {}",
+                          id))?;
 
         let mut item = ItemInfo::new(self.name, id);
-        item.name = name.trim().to_owned();
+        item.name = name;
         item.url = url;
-        item.price = price;
-        item.price_str = price_str;
+        item.currency = self.currency.to_owned();
+        if let Some(price_str) = price_str
+        {
+            item.setPrice(&utils::Money::parse(&price_str, self.currency)?);
+        }
+        item.availability = availability;
         item.update_interval = Some(Duration::from_secs(600));
         Ok(item)
     }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let url = self.itemURL(id);
+        let content: String = http.get(&url).await?;
+        self.parse(id, url, &content)
+    }
+}
+
+/// The Amazon store family, “amazon-*”.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "amazon" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        ["us", "uk", "de", "jp", "ca"].iter().map(|r| r.to_string()).collect()
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Amazon::new(Region::fromCode(region)?).get(http, id).await
+    }
+
+    /// Recognize “/dp/<ASIN>”, “/gp/product/<ASIN>” and
+    /// “/gp/aw/d/<ASIN>” anywhere in the path. The region is from the
+    /// domain.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        let region = Region::fromDomain(&host)?;
+        let id = segs.windows(2).find(|w| w[0] == "dp")
+            .or_else(|| segs.windows(2).find(
+                |w| w[0] == "product" || w[0] == "d"))
+            .map(|w| &w[1])?;
+        if id.len() != 10 || !id.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        Some(ItemKey { store: storeName(self.name(), Some(region)),
+                       id: id.clone() })
+    }
 }
 
 #[cfg(test)]
@@ -126,15 +276,111 @@ mod tests
 {
     use super::*;
     use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    fn parseFixture(region: Region, name: &str) -> Result<ItemInfo, Error>
+    {
+        Amazon::new(region).parse("B08164VTWH", String::new(), &fixture(name))
+    }
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let cases = [
+            (Region::US, "amazon/us.html", "amazon-us", 49999, "$499.99", "USD"),
+            (Region::US, "amazon/us-legacy.html", "amazon-us", 54999, "$549.99",
+             "USD"),
+            (Region::UK, "amazon/uk.html", "amazon-uk", 2499, "£24.99", "GBP"),
+            (Region::DE, "amazon/de.html", "amazon-de", 3699, "€36.99",
+             "EUR"),
+            (Region::JP, "amazon/jp.html", "amazon-jp", 657800, "¥6,578", "JPY"),
+            (Region::CA, "amazon/ca.html", "amazon-ca", 7999, "CA$79.99", "CAD"),
+        ];
+        for (region, name, store, price, price_str, currency) in cases
+        {
+            let item = parseFixture(region, name)?;
+            assert_eq!(item.store, store, "{}", name);
+            assert_eq!(item.price, price, "{}", name);
+            assert_eq!(item.price_str, price_str, "{}", name);
+            assert_eq!(item.currency, currency, "{}", name);
+            assert!(!item.name.is_empty(), "{}", name);
+        }
+        assert_eq!(parseFixture(Region::DE, "amazon/de.html")?.availability,
+                   Availability::InStock);
+        assert_eq!(parseFixture(Region::JP, "amazon/jp.html")?.availability,
+                   Availability::InStock);
+        assert_eq!(parseFixture(Region::US, "amazon/us-legacy.html")?
+                   .availability, Availability::Unknown);
+        let item = parseFixture(Region::US, "amazon/us.html")?;
+        assert_eq!(item.name,
+                   "AMD Ryzen 9 5900X 12-core, 24-Thread Unlocked Desktop Processor");
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_stock() -> Result<(), Error>
+    {
+        let item = parseFixture(Region::US, "amazon/unavailable.html")?;
+        assert_eq!(item.availability, Availability::OutOfStock);
+        assert_eq!(item.price, 0);
+        assert_eq!(item.currency, "USD");
+        let item = parseFixture(Region::JP, "amazon/out-of-stock-jp.html")?;
+        assert_eq!(item.availability, Availability::OutOfStock);
+        assert!(!item.name.is_empty());
+
+        let preorder = fixture("amazon/uk.html")
+            .replace("In stock", "Available to Pre-order now.");
+        let item = Amazon::new(Region::UK).parse("B08164VTWH", String::new(),
+                                                 &preorder)?;
+        assert_eq!(item.availability, Availability::Preorder);
+        assert_eq!(item.price, 2499);
+
+        // Available, but no price to be found
+        let no_price = fixture("amazon/us.html").replace("corePriceDisplay", "x");
+        let e = Amazon::new(Region::US).parse("B08164VTWH", String::new(),
+                                              &no_price).unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+        Ok(())
+    }
 
     #[test]
     fn get_price() -> Result<(), Error>
     {
-        let a = Amazon::new();
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let item = rt.block_on(a.get("B08164VTWH"))?;
-        assert!(item.name.find("5900X").is_some());
-        assert!(item.price > 0);
-        Ok(())
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/dp/B08164VTWH", fixture("amazon/us.html"))]);
+            let client = server.client();
+            let item = Amazon::new(Region::US).get(&client, "B08164VTWH").await?;
+            assert_eq!(item.price, 49999);
+            assert_eq!(item.url, "https://www.amazon.com/dp/B08164VTWH");
+            assert_eq!(server.requests(), vec!["/dp/B08164VTWH"]);
+
+            let e = Amazon::new(Region::US).get(&client, "B000000000").await
+                .unwrap_err();
+            assert_eq!(e.kind(), "not_found");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn parse_url()
+    {
+        let cases = [
+            ("https://www.amazon.co.uk/Pragmatic-Programmer/dp/0135957052/",
+             Some("amazon-uk")),
+            ("https://www.amazon.de/-/en/gp/product/B09HM94VDS?th=1",
+             Some("amazon-de")),
+            ("https://www.amazon.co.jp/%E3%82%BC%E3%83%AB%E3%83%80/dp/B0BVXDZ6QW/",
+             Some("amazon-jp")),
+            ("https://www.amazon.ca/gp/aw/d/B01NAWKYZ0", Some("amazon-ca")),
+            ("https://smile.amazon.com/dp/B08164VTWH", Some("amazon-us")),
+            ("https://www.amazon.fr/dp/B08164VTWH", None),
+        ];
+        for (url, store) in cases
+        {
+            assert_eq!(Backend.parseURL(url).map(|k| k.store).as_deref(), store,
+                       "{}", url);
+        }
     }
 }
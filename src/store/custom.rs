@@ -0,0 +1,272 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use scraper::{Html, Selector};
+use serde_json as json;
+
+use crate::config::CustomStoreParams;
+use crate::data::ItemKey;
+use crate::error::Error;
+use crate::http;
+use crate::store::{ItemInfo, StoreBackend};
+use crate::utils::Money;
+
+enum Extractor
+{
+    Html { name: Selector, price: Selector },
+    Json { name: String, price: String },
+}
+
+/// A store defined in the config file. See `CustomStoreParams`.
+pub struct CustomStore
+{
+    params: CustomStoreParams,
+    extractor: Extractor,
+}
+
+/// Find the “{id}” part of `url` according to `template`.
+fn matchTemplate(template: &str, url: &str) -> Option<String>
+{
+    let (prefix, suffix) = template.split_once("{id}")?;
+    let rest = url.strip_prefix(prefix)?;
+    let end = if suffix.is_empty()
+    {
+        rest.find(['/', '?', '#']).unwrap_or(rest.len())
+    }
+    else
+    {
+        rest.find(suffix)?
+    };
+    let id = &rest[..end];
+    if id.is_empty() || id.contains('/')
+    {
+        None
+    }
+    else
+    {
+        Some(id.to_owned())
+    }
+}
+
+fn parseSelector(s: &str) -> Result<Selector, Error>
+{
+    Selector::parse(s).map_err(
+        |e| error!(Config, "Invalid CSS selector {}: {}", s, e))
+}
+
+impl CustomStore
+{
+    pub fn new(params: CustomStoreParams) -> Result<Self, Error>
+    {
+        if params.name.is_empty()
+        {
+            return Err(error!(Config, "Custom store without a name"));
+        }
+        if !params.url_template.contains("{id}")
+        {
+            return Err(error!(Config, "URL template of store {} has no {{id}}",
+                              params.name));
+        }
+        let extractor = match (&params.name_selector, &params.price_selector,
+                               &params.name_pointer, &params.price_pointer)
+        {
+            (Some(name), Some(price), None, None) => Extractor::Html {
+                name: parseSelector(name)?,
+                price: parseSelector(price)?,
+            },
+            (None, None, Some(name), Some(price)) => Extractor::Json {
+                name: name.clone(),
+                price: price.clone(),
+            },
+            _ => return Err(error!(
+                Config,
+                "Store {} needs either name_selector and price_selector, \
+                 or name_pointer and price_pointer", params.name)),
+        };
+        Ok(Self { params, extractor })
+    }
+
+    fn pageURL(&self, id: &str) -> String
+    {
+        self.params.page_url_template.as_ref()
+            .unwrap_or(&self.params.url_template).replace("{id}", id)
+    }
+
+    /// Extract name and price from the fetched content.
+    fn extract(&self, content: &str) -> Result<(String, Money), Error>
+    {
+        let currency = &self.params.currency;
+        match &self.extractor
+        {
+            Extractor::Html { name, price } =>
+            {
+                let document = Html::parse_document(content);
+                let text = |sel: &Selector, what: &str| {
+                    document.select(sel).next()
+                        .map(|node| node.text().collect::<String>()
+                             .trim().to_owned())
+                        .ok_or_else(|| error!(StoreChanged,
+                                              "Failed to find {} in {}",
+                                              what, self.params.name))
+                };
+                Ok((text(name, "name")?,
+                    Money::parse(&text(price, "price")?, currency)?))
+            },
+            Extractor::Json { name, price } =>
+            {
+                let data: json::Value = json::from_str(content).map_err(
+                    |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+                let name = data.pointer(name).and_then(|v| v.as_str())
+                    .ok_or_else(|| error!(StoreChanged,
+                                          "Failed to find name in {}",
+                                          self.params.name))?;
+                let price = data.pointer(price).ok_or_else(
+                    || error!(StoreChanged, "Failed to find price in {}",
+                              self.params.name))?;
+                Ok((name.to_owned(), Money::fromJSON(price, currency)?))
+            },
+        }
+    }
+
+    fn parse(&self, id: &str, content: &str) -> Result<ItemInfo, Error>
+    {
+        let (name, price) = self.extract(content)?;
+
+        let mut item = ItemInfo::new(&self.params.name, id);
+        item.name = name;
+        item.url = self.pageURL(id);
+        item.setPrice(&price);
+        item.update_interval = self.params.update_interval_sec
+            .map(Duration::from_secs);
+        Ok(item)
+    }
+}
+
+#[async_trait]
+impl StoreBackend for CustomStore
+{
+    fn name(&self) -> &str { &self.params.name }
+
+    fn regions(&self) -> Vec<String> { Vec::new() }
+
+    async fn get(&self, http: &http::StoreClient, _region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        let url = self.params.url_template.replace("{id}", id);
+        let content = http.get(&url).await?;
+        self.parse(id, &content)
+    }
+
+    /// Match the URL against the page URL template and the URL
+    /// template.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        self.params.page_url_template.iter()
+            .chain(std::iter::once(&self.params.url_template))
+            .find_map(|t| matchTemplate(t, url))
+            .map(|id| ItemKey { store: self.params.name.clone(), id })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::config::ConfigParams;
+    use crate::testing::{fixture, MockServer};
+
+    fn storesFromToml(s: &str) -> Result<Vec<CustomStore>, Error>
+    {
+        let conf: ConfigParams = toml::from_str(s).unwrap();
+        conf.custom_store.into_iter().map(CustomStore::new).collect()
+    }
+
+    const BASE_CONF: &str = r#"
+port = 8000
+db_file = "wishlist.db"
+update_interval_sec = 3600
+"#;
+
+    #[test]
+    fn html_store() -> Result<(), Error>
+    {
+        let stores = storesFromToml(&format!("{}{}", BASE_CONF, r##"
+[[custom_store]]
+name = "shop"
+url_template = "https://shop.example.com/item/{id}"
+name_selector = "h1.title"
+price_selector = "#price .amount"
+currency = "USD"
+update_interval_sec = 600
+"##))?;
+        let item = stores[0].parse("abc", &fixture("custom/shop-item.html"))?;
+        assert_eq!(item.store, "shop");
+        assert_eq!(item.name, "Some Game");
+        assert_eq!(item.url, "https://shop.example.com/item/abc");
+        assert_eq!(item.price, 129999);
+        assert_eq!(item.price_str, "$1,299.99");
+        assert_eq!(item.currency, "USD");
+        assert_eq!(item.update_interval, Some(Duration::from_secs(600)));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let item = rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/item/abc", fixture("custom/shop-item.html"))]);
+            stores[0].get(&server.client(), None, "abc").await
+        })?;
+        assert_eq!(item.price, 129999);
+        Ok(())
+    }
+
+    #[test]
+    fn json_store() -> Result<(), Error>
+    {
+        let stores = storesFromToml(&format!("{}{}", BASE_CONF, r#"
+[[custom_store]]
+name = "api-shop"
+url_template = "https://api.example.com/products/{id}.json"
+page_url_template = "https://example.com/p/{id}"
+name_pointer = "/product/title"
+price_pointer = "/product/offers/0/price"
+currency = "EUR"
+"#))?;
+        let content = r#"{"product": {"title": "Thing",
+                          "offers": [{"price": 19.5}]}}"#;
+        let item = stores[0].parse("42", content)?;
+        assert_eq!(item.name, "Thing");
+        assert_eq!(item.url, "https://example.com/p/42");
+        assert_eq!(item.price, 1950);
+        assert_eq!(item.price_str, "€19.50");
+        assert_eq!(item.currency, "EUR");
+        assert_eq!(item.update_interval, None);
+
+        let key = stores[0].parseURL("https://example.com/p/42?ref=abc");
+        assert_eq!(key.map(|k| k.id), Some(String::from("42")));
+        let key = stores[0].parseURL(
+            "https://api.example.com/products/42.json");
+        assert_eq!(key.map(|k| k.id), Some(String::from("42")));
+        assert!(stores[0].parseURL("https://example.com/q/42").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_store()
+    {
+        assert!(storesFromToml(&format!("{}{}", BASE_CONF, r#"
+[[custom_store]]
+name = "shop"
+url_template = "https://shop.example.com/item/{id}"
+name_selector = "h1"
+price_pointer = "/price"
+currency = "USD"
+"#)).is_err());
+        assert!(storesFromToml(&format!("{}{}", BASE_CONF, r#"
+[[custom_store]]
+name = "shop"
+url_template = "https://shop.example.com/item/"
+name_selector = "h1"
+price_selector = ".price"
+currency = "USD"
+"#)).is_err());
+    }
+}
@@ -1,7 +1,10 @@
+use async_trait::async_trait;
 use serde_json as json;
 
+use crate::data::ItemKey;
 use crate::error::Error;
-use crate::store::ItemInfo;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend};
 use crate::utils;
 
 pub enum Region
@@ -9,10 +12,23 @@ pub enum Region
     US,
 }
 
+impl Region
+{
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
+    {
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            _ => Err(error!(NotFound, "Invalid Epic region: {:?}", code)),
+        }
+    }
+}
+
 pub struct Epic
 {
     region: &'static str,
     name: &'static str,
+    currency: &'static str,
 }
 
 impl Epic
@@ -21,7 +37,8 @@ impl Epic
     {
         match region
         {
-            Region::US => Self{ region: "en-US", name: "epic-us" },
+            Region::US => Self{ region: "en-US", name: "epic-us",
+                                currency: "USD" },
         }
     }
 
@@ -30,33 +47,88 @@ impl Epic
         format!("https://epicgames.com/store/{}/p/{}", self.region, id)
     }
 
-    pub async fn get(&self, id: &str) -> Result<ItemInfo, Error>
+    /// Extract the item from its product page at `url`.
+    fn parse(&self, id: &str, url: String, content: &str) ->
+        Result<ItemInfo, Error>
     {
         // The items in Epic store are very well-defined. The info is
         // embeded in the HTML as Linked Data JSON.
-        let url = self.dataURL(id);
-        let content = utils::get(&url).await?;
         let (s, _, _) = utils::findSubStr(
-            &content, "type=\"application/ld+json\"", "</script>").ok_or_else(
-            || rterr!("Invalid Epic item page"))?;
+            content, "type=\"application/ld+json\"", "</script>").ok_or_else(
+            || error!(StoreChanged, "Invalid Epic item page"))?;
         let begin = s.find("{").ok_or_else(
-            || rterr!("Failed to find beginning of JSON"))?;
+            || error!(StoreChanged, "Failed to find beginning of JSON"))?;
         let json_str = &s[begin..];
 
         let data: json::Value = serde_json::from_str(json_str).map_err(
-            |_| rterr!("Failed to parse JSON"))?;
-        let price = data["offers"][0]["priceSpecification"]["price"].as_f64()
-            .ok_or_else(|| rterr!("Failed to extract price"))?;
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let offer = &data["offers"][0];
+        let price_spec = &offer["priceSpecification"];
+        let currency = price_spec["priceCurrency"].as_str()
+            .unwrap_or(self.currency);
+        if price_spec["price"].is_null()
+        {
+            return Err(error!(StoreChanged, "Failed to extract price"));
+        }
+        let price = utils::Money::fromJSON(&price_spec["price"], currency)?;
         let name = data["name"].as_str().ok_or_else(
-            || rterr!("Failed to extract name"))?;
+            || error!(StoreChanged, "Failed to extract name"))?;
 
         let mut item = ItemInfo::new(self.name, id);
         item.name = name.to_owned();
-        item.price = (price * 100.0) as i64;
-        item.price_str = format!("${}", price);
+        item.setPrice(&price);
+        item.availability = offer["availability"].as_str()
+            .map(Availability::fromSchemaOrg).unwrap_or_default();
         item.url = url;
         Ok(item)
     }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let url = self.dataURL(id);
+        let content = http.get(&url).await?;
+        self.parse(id, url, &content)
+    }
+}
+
+/// The Epic Games store family, “epic-*”.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "epic" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        vec![String::from("us")]
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Epic::new(Region::fromCode(region)?).get(http, id).await
+    }
+
+    /// Recognize “epicgames.com/store/<locale>/p/<id>” and
+    /// “store.epicgames.com/<locale>/p/<id>”. The locale only decides
+    /// the language, so all of them are in the US store.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        let segs = match host.as_str()
+        {
+            "epicgames.com" if segs.first()? == "store" => &segs[1..],
+            "store.epicgames.com" => &segs[..],
+            _ => return None,
+        };
+        if segs.len() < 3 || segs[1] != "p"
+        {
+            return None;
+        }
+        Some(ItemKey { store: String::from("epic-us"), id: segs[2].clone() })
+    }
 }
 
 #[cfg(test)]
@@ -64,15 +136,39 @@ mod tests
 {
     use super::*;
     use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let item = Epic::new(Region::US).parse(
+            "hitman-3", String::new(), &fixture("epic/hitman-3.html"))?;
+        assert_eq!(item.store, "epic-us");
+        assert_eq!(item.name, "HITMAN 3");
+        // Not 1998, as (19.99 * 100.0) as i64 would be.
+        assert_eq!(item.price, 1999);
+        assert_eq!(item.price_str, "$19.99");
+        assert_eq!(item.currency, "USD");
+        assert_eq!(item.availability, Availability::InStock);
+
+        let e = Epic::new(Region::US).parse("hitman-3", String::new(),
+                                            "<html></html>").unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+        Ok(())
+    }
 
     #[test]
     fn get_price() -> Result<(), Error>
     {
-        let a = Epic::new(Region::US);
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let item = rt.block_on(a.get("hitman-3"))?;
-        assert!(item.name.find("HITMAN 3").is_some());
-        assert!(item.price > 0);
-        Ok(())
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/store/en-US/p/hitman-3", fixture("epic/hitman-3.html"))]);
+            let item = Epic::new(Region::US).get(&server.client(), "hitman-3")
+                .await?;
+            assert_eq!(item.name, "HITMAN 3");
+            assert_eq!(item.url, "https://epicgames.com/store/en-US/p/hitman-3");
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use serde_json as json;
+
+use crate::error::Error;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend};
+use crate::utils;
+
+pub enum Region
+{
+    US,
+    EU,
+    UK,
+    AU,
+}
+
+impl Region
+{
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
+    {
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            Some("eu") => Ok(Self::EU),
+            Some("uk") => Ok(Self::UK),
+            Some("au") => Ok(Self::AU),
+            _ => Err(error!(NotFound, "Invalid GOG region: {:?}", code)),
+        }
+    }
+}
+
+pub struct Gog
+{
+    country: &'static str,
+    name: &'static str,
+    currency: &'static str,
+}
+
+impl Gog
+{
+    pub fn new(region: Region) -> Self
+    {
+        match region
+        {
+            Region::US => Self{ country: "US", name: "gog-us", currency: "USD" },
+            Region::EU => Self{ country: "DE", name: "gog-eu", currency: "EUR" },
+            Region::UK => Self{ country: "GB", name: "gog-uk", currency: "GBP" },
+            Region::AU => Self{ country: "AU", name: "gog-au", currency: "AUD" },
+        }
+    }
+
+    /// A price in the price API, e.g. “4999 EUR”, in the smallest
+    /// unit of the currency.
+    fn parseAmount(s: &str) -> Result<utils::Money, Error>
+    {
+        let (amount, currency) = s.split_once(' ').ok_or_else(
+            || error!(StoreChanged, "Invalid price: {}", s))?;
+        let amount: i64 = amount.parse().map_err(
+            |e| error_from!(Parse, e, "Invalid price: {}", s))?;
+        let scale = if utils::currencyDecimals(currency) == 0 { 100 } else { 1 };
+        Ok(utils::Money::new(amount * scale, currency))
+    }
+
+    /// Extract the item from the responses of the product API and the
+    /// price API. A country may have prices in several currencies; only
+    /// the one of the region counts, and the item is not sold in the
+    /// region without it.
+    fn parse(&self, id: &str, product: &str, prices: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let product: json::Value = serde_json::from_str(product).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let prices: json::Value = serde_json::from_str(prices).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let name = product["title"].as_str().ok_or(
+            error!(StoreChanged, "Failed to extract name"))?;
+
+        let mut item = ItemInfo::new(self.name, id);
+        item.name = name.to_owned();
+        item.url = product["links"]["product_card"].as_str().unwrap_or_default()
+            .to_owned();
+        item.currency = self.currency.to_owned();
+
+        let prices = prices["_embedded"]["prices"].as_array().ok_or(
+            error!(StoreChanged, "Failed to get prices"))?;
+        let price = match prices.iter().find(
+            |p| p["currency"]["code"].as_str() == Some(self.currency))
+        {
+            Some(p) => p,
+            None =>
+            {
+                // Not sold in the country, or only in other currencies
+                item.availability = Availability::Delisted;
+                return Ok(item);
+            },
+        };
+        let final_price = price["finalPrice"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get price"))?;
+        item.setPrice(&Self::parseAmount(final_price)?);
+        if let Some(base) = price["basePrice"].as_str()
+        {
+            item.setRegularPrice(&Self::parseAmount(base)?);
+        }
+        item.availability = if product["is_pre_order"].as_bool() == Some(true)
+        {
+            Availability::Preorder
+        }
+        else
+        {
+            Availability::InStock
+        };
+        Ok(item)
+    }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(error!(InvalidInput, "Invalid GOG product ID: {}", id));
+        }
+        let req = http.request(&format!("https://api.gog.com/products/{}", id))
+            .query(&[("locale", "en-US")]);
+        let product = http.send(req).await?;
+        let req = http.request(
+            &format!("https://api.gog.com/products/{}/prices", id))
+            .query(&[("countryCode", self.country)]);
+        let prices = http.send(req).await?;
+        self.parse(id, &product, &prices)
+    }
+}
+
+/// The GOG store family, “gog-*”. The pages of GOG are by slug, which
+/// the product API does not take, so URLs are not recognized.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "gog" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        ["us", "eu", "uk", "au"].iter().map(|r| r.to_string()).collect()
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Gog::new(Region::fromCode(region)?).get(http, id).await
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let product = fixture("gog/product-1207664643.json");
+        let prices = fixture("gog/prices-1207664643.json");
+        let item = Gog::new(Region::EU).parse("1207664643", &product, &prices)?;
+        assert_eq!(item.store, "gog-eu");
+        assert_eq!(item.name, "The Witcher 3: Wild Hunt - Game of the Year Edition");
+        assert_eq!(item.price, 999);
+        assert_eq!(item.currency, "EUR");
+        assert_eq!(item.regular_price, Some(4999));
+        assert_eq!(item.discount_percent, Some(80));
+        assert_eq!(item.availability, Availability::InStock);
+        assert_eq!(item.url, "https://www.gog.com/game/\
+                              the_witcher_3_wild_hunt_game_of_the_year_edition");
+
+        // No price in the currency of the region
+        let item = Gog::new(Region::UK).parse("1207664643", &product, &prices)?;
+        assert_eq!(item.availability, Availability::Delisted);
+        assert_eq!(item.currency, "GBP");
+        assert_eq!(item.price, 0);
+
+        let item = Gog::new(Region::US).parse(
+            "1207664643", &product, r#"{"_embedded": {"prices": []}}"#)?;
+        assert_eq!(item.availability, Availability::Delisted);
+        assert_eq!(item.currency, "USD");
+        let e = Gog::new(Region::US).parse("1207664643", &product, "{}")
+            .unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+
+        assert_eq!(Gog::parseAmount("1980 JPY")?.amount, 198000);
+        assert!(Gog::parseAmount("19.99").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn get_price() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/products/1207664643", fixture("gog/product-1207664643.json")),
+                ("/products/1207664643/prices",
+                 fixture("gog/prices-1207664643.json"))]);
+            let item = Gog::new(Region::EU).get(&server.client(), "1207664643")
+                .await?;
+            assert_eq!(item.price, 999);
+            assert_eq!(server.requests(), vec![
+                "/products/1207664643?locale=en-US",
+                "/products/1207664643/prices?countryCode=DE"]);
+            assert_eq!(Gog::new(Region::EU).get(&server.client(), "witcher-3")
+                       .await.unwrap_err().kind(), "invalid_input");
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+use scraper::{Html, Selector};
+use serde_json as json;
+
+use crate::data::ItemKey;
+use crate::error::Error;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend};
+use crate::utils;
+
+/// The Humble Store. It has no regions: the currency is picked by
+/// where the visitor is.
+pub struct Humble;
+
+impl Humble
+{
+    fn pageURL(id: &str) -> String
+    {
+        format!("https://www.humblebundle.com/store/{}", id)
+    }
+
+    /// Extract the item from its store page. The product is in a JSON
+    /// script in the page.
+    fn parse(id: &str, content: &str) -> Result<ItemInfo, Error>
+    {
+        let document = Html::parse_document(content);
+        let sel = Selector::parse("script#storefront-webpack-json-data").unwrap();
+        let script = document.select(&sel).next().ok_or(
+            error!(StoreChanged, "Failed to find store data"))?;
+        let data: json::Value = serde_json::from_str(
+            &script.text().collect::<String>()).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let product = &data["productData"];
+        let name = product["human_name"].as_str().ok_or(
+            error!(StoreChanged, "Failed to extract name"))?;
+
+        let mut item = ItemInfo::new("humble", id);
+        item.name = name.to_owned();
+        item.url = Self::pageURL(id);
+
+        let price = &product["current_price"];
+        if price.is_null() || product["disable_purchase"].as_bool() == Some(true)
+        {
+            item.currency = product["full_price"]["currency"].as_str()
+                .unwrap_or("USD").to_owned();
+            item.availability = Availability::Delisted;
+            return Ok(item);
+        }
+        let currency = price["currency"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get currency"))?;
+        item.setPrice(&utils::Money::fromJSON(&price["amount"], currency)?);
+        let full = &product["full_price"];
+        if !full.is_null()
+        {
+            item.setRegularPrice(&utils::Money::fromJSON(&full["amount"], currency)?);
+            if item.discount_percent.is_some()
+            {
+                item.sale_end = product["sale_end"].as_i64()
+                    .map(utils::timestampToUtcTime);
+            }
+        }
+        item.availability = if product["coming_soon"].as_bool() == Some(true)
+        {
+            Availability::Preorder
+        }
+        else
+        {
+            Availability::InStock
+        };
+        Ok(item)
+    }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let content = http.get(&Self::pageURL(id)).await?;
+        Self::parse(id, &content)
+    }
+}
+
+/// The Humble Store, “humble”.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "humble" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        Vec::new()
+    }
+
+    async fn get(&self, http: &http::StoreClient, _region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Humble.get(http, id).await
+    }
+
+    /// Recognize “humblebundle.com/store/<id>”.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        if host != "humblebundle.com" || segs.len() != 2 || segs[0] != "store"
+        {
+            return None;
+        }
+        Some(ItemKey { store: String::from("humble"), id: segs[1].clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let item = Humble::parse("hades", &fixture("humble/hades.html"))?;
+        assert_eq!(item.store, "humble");
+        assert_eq!(item.name, "Hades");
+        assert_eq!(item.price, 999);
+        assert_eq!(item.price_str, "$9.99");
+        assert_eq!(item.regular_price, Some(2499));
+        assert_eq!(item.discount_percent, Some(60));
+        assert_eq!(item.sale_end, Some(utils::timestampToUtcTime(1716310800)));
+        assert_eq!(item.availability, Availability::InStock);
+        assert_eq!(item.url, "https://www.humblebundle.com/store/hades");
+
+        let item = Humble::parse("hades", &fixture("humble/unavailable.html"))?;
+        assert_eq!(item.availability, Availability::Delisted);
+        assert_eq!(item.price, 0);
+        assert_eq!(item.currency, "USD");
+
+        let e = Humble::parse("hades", "<html></html>").unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+        Ok(())
+    }
+
+    #[test]
+    fn get_price() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/store/hades", fixture("humble/hades.html"))]);
+            let item = Humble.get(&server.client(), "hades").await?;
+            assert_eq!(item.name, "Hades");
+            assert_eq!(Humble.get(&server.client(), "celeste").await
+                       .unwrap_err().kind(), "not_found");
+            Ok(())
+        })
+    }
+}
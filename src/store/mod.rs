@@ -1,15 +1,103 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 
+use async_trait::async_trait;
+
 use chrono;
 use serde::{Deserialize, Serialize};
 use chrono::serde::ts_seconds::deserialize as from_ts;
 use chrono::serde::ts_seconds::serialize as to_ts;
+use chrono::serde::ts_seconds_option::deserialize as from_ts_opt;
+use chrono::serde::ts_seconds_option::serialize as to_ts_opt;
 
 use crate::error::Error;
+use crate::data::ItemKey;
+use crate::config::ConfigParams;
+use crate::http;
+use crate::utils::Money;
 mod playstation;
 mod switch;
 mod amazon;
 mod epic;
+mod steam;
+mod gog;
+mod humble;
+mod xbox;
+mod custom;
+
+/// Whether an item can be bought.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability
+{
+    InStock,
+    OutOfStock,
+    /// Can be ordered, but not released yet.
+    Preorder,
+    /// The store does not sell the item anymore.
+    Delisted,
+    /// The store does not say.
+    #[default]
+    Unknown,
+}
+
+impl Availability
+{
+    /// The name of the availability in the database and the API,
+    /// e.g. “in_stock”.
+    pub fn code(&self) -> &'static str
+    {
+        match self
+        {
+            Self::InStock => "in_stock",
+            Self::OutOfStock => "out_of_stock",
+            Self::Preorder => "preorder",
+            Self::Delisted => "delisted",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Inverse of `code()`. Anything unrecognized is `Unknown`.
+    pub fn fromCode(code: &str) -> Self
+    {
+        match code
+        {
+            "in_stock" => Self::InStock,
+            "out_of_stock" => Self::OutOfStock,
+            "preorder" => Self::Preorder,
+            "delisted" => Self::Delisted,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// From a schema.org ItemAvailability, e.g.
+    /// “https://schema.org/InStock”, with or without the URL part.
+    pub fn fromSchemaOrg(value: &str) -> Self
+    {
+        match value.rsplit('/').next().unwrap_or_default()
+        {
+            "InStock" | "InStoreOnly" | "OnlineOnly" | "LimitedAvailability" =>
+                Self::InStock,
+            "OutOfStock" | "SoldOut" => Self::OutOfStock,
+            "PreOrder" | "PreSale" => Self::Preorder,
+            "Discontinued" => Self::Delisted,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Whether the item can be ordered now.
+    pub fn canOrder(&self) -> bool
+    {
+        matches!(self, Self::InStock | Self::Preorder)
+    }
+
+    /// Whether the store says the item cannot be ordered now.
+    pub fn cannotOrder(&self) -> bool
+    {
+        matches!(self, Self::OutOfStock | Self::Delisted)
+    }
+}
 
 /// An abstraction for the info one get when querying a store.
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -21,12 +109,42 @@ pub struct ItemInfo
     pub url: String,
     pub price: i64,                 // Price * 100
     pub price_str: String,
+    // ISO 4217 code of the currency of the price, e.g. “USD”.
+    pub currency: String,
+    // If the item cannot be ordered, the store may not show a price,
+    // in which case the price is 0.
+    pub availability: Availability,
+    // Price * 100 without any discount, if the store shows it. `price`
+    // is what one pays now, which is lower during a sale.
+    pub regular_price: Option<i64>,
+    // How far `price` is below `regular_price`, if it is.
+    pub discount_percent: Option<u32>,
+    // When the sale ends, if the item is on sale and the store says.
+    #[serde(deserialize_with = "from_ts_opt", serialize_with = "to_ts_opt")]
+    pub sale_end: Option<chrono::DateTime<chrono::Utc>>,
+    // Price * 100 for members of the subscription of the store, e.g.
+    // PlayStation Plus, if it is lower than `price`.
+    pub member_price: Option<i64>,
     // Price * 100. If price is lower than this, trigger an alerm.
     pub alert_price: Option<u64>,
+    // Trigger an alarm if price drops by this percent at once.
+    pub alert_drop_percent: Option<u32>,
+    // Trigger an alarm if price is lower than ever before.
+    pub alert_all_time_low: bool,
+    // Trigger an alarm if the item can be ordered again.
+    pub alert_back_in_stock: bool,
     // Time between price updates.
     pub update_interval: Option<Duration>,
     #[serde(deserialize_with = "from_ts", serialize_with = "to_ts")]
     pub last_update: chrono::DateTime<chrono::Utc>,
+    // Last time the store was queried, whether the price changed or
+    // not.
+    #[serde(deserialize_with = "from_ts", serialize_with = "to_ts")]
+    pub last_check: chrono::DateTime<chrono::Utc>,
+    // Number of failed queries in a row.
+    pub failure_count: u32,
+    // Why the last query failed, if it did.
+    pub last_error: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -41,64 +159,360 @@ impl ItemInfo
             url: String::new(),
             price: 0,
             price_str: String::new(),
+            currency: String::new(),
+            availability: Availability::Unknown,
+            regular_price: None,
+            discount_percent: None,
+            sale_end: None,
+            member_price: None,
             alert_price: None,
+            alert_drop_percent: None,
+            alert_all_time_low: false,
+            alert_back_in_stock: false,
             update_interval: None,
             last_update: chrono::Utc::now(),
+            last_check: chrono::Utc::now(),
+            failure_count: 0,
+            last_error: None,
         }
     }
 
+    /// When the price should be queried next. Use `default_interval`
+    /// if the item does not have its own update interval.
+    pub fn nextUpdate(&self, default_interval: Duration) ->
+        chrono::DateTime<chrono::Utc>
+    {
+        let interval = self.update_interval.unwrap_or(default_interval);
+        self.last_check + chrono::Duration::seconds(interval.as_secs() as i64)
+    }
+
+    /// Set the price, its currency, and a formatted price string.
+    pub fn setPrice(&mut self, price: &Money)
+    {
+        self.price = price.amount;
+        self.price_str = price.to_string();
+        self.currency = price.currency.clone();
+    }
+
+    /// Set the regular price, and the discount from it. Call after
+    /// `setPrice()`.
+    pub fn setRegularPrice(&mut self, regular: &Money)
+    {
+        self.regular_price = Some(regular.amount);
+        self.discount_percent = if regular.amount > self.price && self.price >= 0
+        {
+            let off = regular.amount - self.price;
+            Some(((off * 100 + regular.amount / 2) / regular.amount) as u32)
+        }
+        else
+        {
+            None
+        };
+    }
+
     pub fn bare(&self) -> bool
     {
         self.name.is_empty()
     }
 }
 
-macro_rules! store_enum
+/// A family of stores, possibly with multiple regions. Each region
+/// is exposed as a separate store named “<name>-<region>”, e.g.
+/// “ps4-us”. A store without regions is simply named “<name>”.
+#[async_trait]
+pub trait StoreBackend: Send + Sync
+{
+    /// Name of the store family, e.g. “ps4”.
+    fn name(&self) -> &str;
+
+    /// Supported regions, e.g. “us”. Empty if the store is not
+    /// regional.
+    fn regions(&self) -> Vec<String>;
+
+    /// Query the store for the item with `id` using `http`. `region`
+    /// is one of `regions()`, or None if the store is not regional.
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>;
+
+    /// Figure out the item from the URL of its product page. Return
+    /// None if the URL does not belong to this store.
+    fn parseURL(&self, _url: &str) -> Option<ItemKey>
+    {
+        None
+    }
+}
+
+/// Full store name of a region in a store family.
+pub fn storeName(name: &str, region: Option<&str>) -> String
+{
+    match region
+    {
+        Some(r) => format!("{}-{}", name, r),
+        None => name.to_owned(),
+    }
+}
+
+/// All the stores that could be queried, keyed by the full store
+/// name.
+pub struct Registry
+{
+    stores: HashMap<String, (Arc<dyn StoreBackend>, Option<String>)>,
+    backends: Vec<Arc<dyn StoreBackend>>,
+    http: Arc<http::Client>,
+}
+
+impl Registry
 {
+    /// A registry with all the builtin stores, queried with `http`.
+    pub fn new(http: Arc<http::Client>) -> Self
     {
-        $( $sub_name:ident($store_type:ty),)+
-    } => {
-        pub enum Store
+        let mut registry = Self::empty(http);
+        // None of the builtin stores clash.
+        registry.register(Arc::new(playstation::Backend)).unwrap();
+        registry.register(Arc::new(switch::Backend)).unwrap();
+        registry.register(Arc::new(amazon::Backend)).unwrap();
+        registry.register(Arc::new(epic::Backend)).unwrap();
+        registry.register(Arc::new(steam::Backend)).unwrap();
+        registry.register(Arc::new(gog::Backend)).unwrap();
+        registry.register(Arc::new(humble::Backend)).unwrap();
+        registry.register(Arc::new(xbox::Backend)).unwrap();
+        registry
+    }
+
+    /// A registry with all the builtin stores and the custom stores
+    /// defined in the config, queried with `http`.
+    pub fn fromConfig(conf: &ConfigParams, http: Arc<http::Client>) ->
+        Result<Self, Error>
+    {
+        let mut registry = Self::new(http);
+        for params in &conf.custom_store
+        {
+            registry.register(Arc::new(
+                custom::CustomStore::new(params.clone())?))?;
+        }
+        Ok(registry)
+    }
+
+    pub fn empty(http: Arc<http::Client>) -> Self
+    {
+        Self { stores: HashMap::new(), backends: Vec::new(), http }
+    }
+
+    /// The HTTP client of the stores.
+    pub fn http(&self) -> Arc<http::Client>
+    {
+        self.http.clone()
+    }
+
+    /// Add all regions of a store family. Fail if any of the store
+    /// names is already registered, in which case nothing is added.
+    pub fn register(&mut self, backend: Arc<dyn StoreBackend>) ->
+        Result<(), Error>
+    {
+        let regions = backend.regions();
+        let names: Vec<(String, Option<String>)> = if regions.is_empty()
         {
-            $( $sub_name($store_type), )+
+            vec![(backend.name().to_owned(), None)]
         }
+        else
+        {
+            regions.into_iter().map(
+                |r| (storeName(backend.name(), Some(&r)), Some(r))).collect()
+        };
 
-        impl Store
+        if let Some((name, _)) = names.iter().find(
+            |(name, _)| self.stores.contains_key(name))
+        {
+            return Err(error!(Config, "Store {} already registered", name));
+        }
+        for (name, region) in names
         {
-            pub async fn get(&self, id: &str) -> Result<ItemInfo, Error>
-            {
-                match self
-                {
-                    $( Self::$sub_name(s) => s.get(id).await, )+
-                }
-            }
+            self.stores.insert(name, (backend.clone(), region));
         }
+        self.backends.push(backend);
+        Ok(())
+    }
+
+    /// Find the item from the URL of its product page.
+    pub fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        self.backends.iter().filter_map(|b| b.parseURL(url))
+            .find(|key| self.stores.contains_key(&key.store))
     }
 }
 
-store_enum!
+/// A store of a specific region.
+pub struct Store
 {
-    PlayStation(playstation::PlayStation),
-    Switch(switch::Switch),
-    Amazon(amazon::Amazon),
-    Epic(epic::Epic),
+    backend: Arc<dyn StoreBackend>,
+    region: Option<String>,
+    http: http::StoreClient,
 }
 
 impl Store
 {
-    pub fn new(store_name: &str) -> Result<Self, Error>
+    pub fn new(store_name: &str, registry: &Registry) -> Result<Self, Error>
+    {
+        let (backend, region) = registry.stores.get(store_name).ok_or_else(
+            || error!(NotFound, "Invalid store: {}", store_name))?;
+        Ok(Self {
+            backend: backend.clone(),
+            region: region.clone(),
+            http: registry.http.forStore(store_name, backend.name()),
+        })
+    }
+
+    /// Name of the store family, e.g. “ps4” for “ps4-us”.
+    pub fn family(&self) -> &str
+    {
+        self.backend.name()
+    }
+
+    pub async fn get(&self, id: &str) -> Result<ItemInfo, Error>
+    {
+        self.backend.get(&self.http, self.region.as_deref(), id).await
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    struct Dummy
+    {
+        name: &'static str,
+        regions: Vec<String>,
+    }
+
+    #[async_trait]
+    impl StoreBackend for Dummy
+    {
+        fn name(&self) -> &str { self.name }
+        fn regions(&self) -> Vec<String> { self.regions.clone() }
+        async fn get(&self, _http: &http::StoreClient, region: Option<&str>,
+                     id: &str) -> Result<ItemInfo, Error>
+        {
+            let mut item = ItemInfo::new(&storeName(self.name, region), id);
+            item.name = String::from("dummy");
+            Ok(item)
+        }
+    }
+
+    fn newRegistry() -> Registry
+    {
+        Registry::new(Arc::new(
+            http::Client::new(&crate::config::HttpParams::default()).unwrap()))
+    }
+
+    #[test]
+    fn regular_price()
+    {
+        let mut item = ItemInfo::new("switch-us", "aaa");
+        item.setPrice(&Money::new(749, "USD"));
+        item.setRegularPrice(&Money::new(1499, "USD"));
+        assert_eq!(item.regular_price, Some(1499));
+        assert_eq!(item.discount_percent, Some(50));
+        item.setPrice(&Money::new(1399, "USD"));
+        item.setRegularPrice(&Money::new(1999, "USD"));
+        assert_eq!(item.discount_percent, Some(30));
+        item.setRegularPrice(&Money::new(1399, "USD"));
+        assert_eq!(item.discount_percent, None);
+    }
+
+    #[test]
+    fn builtin_stores()
+    {
+        let registry = newRegistry();
+        for name in ["ps4-us", "ps4-hk", "switch-us", "switch-eu", "switch-jp",
+                     "amazon-us", "amazon-jp", "epic-us", "steam-us", "steam-eu",
+                     "gog-us", "gog-uk", "humble", "xbox-us", "xbox-jp"]
+        {
+            assert!(Store::new(name, &registry).is_ok());
+        }
+        assert!(Store::new("ps4", &registry).is_err());
+        assert!(Store::new("steam", &registry).is_err());
+        assert!(Store::new("steam-xx", &registry).is_err());
+        assert!(Store::new("gog-jp", &registry).is_err());
+        assert!(Store::new("humble-us", &registry).is_err());
+    }
+
+    #[test]
+    fn register_store() -> Result<(), Error>
+    {
+        let mut registry = newRegistry();
+        registry.register(Arc::new(Dummy {
+            name: "dummy", regions: vec![String::from("us"), String::from("jp")]
+        }))?;
+        registry.register(Arc::new(Dummy { name: "local", regions: Vec::new() }))?;
+        assert!(registry.register(Arc::new(Dummy {
+            name: "dummy", regions: vec![String::from("jp")]
+        })).is_err());
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let item = rt.block_on(Store::new("dummy-jp", &registry)?.get("id"))?;
+        assert_eq!(item.store, "dummy-jp");
+        let item = rt.block_on(Store::new("local", &registry)?.get("id"))?;
+        assert_eq!(item.store, "local");
+        assert!(Store::new("dummy", &registry).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_url()
     {
-        match store_name
+        let registry = newRegistry();
+        let cases = [
+            ("https://www.amazon.com/AmazonBasics-Shaped-Silicone-Combo-Black/dp/B07LBDVJKN/",
+             Some(("amazon-us", "B07LBDVJKN"))),
+            ("https://amazon.com/gp/product/B08164VTWH?th=1",
+             Some(("amazon-us", "B08164VTWH"))),
+            ("https://store.playstation.com/en-us/product/UP0102-PPSA01556_00-VILLAGEFULLGAMEX",
+             Some(("ps4-us", "UP0102-PPSA01556_00-VILLAGEFULLGAMEX"))),
+            ("https://store.playstation.com/zh-hant-hk/product/HP0102-CUSA18072_00-RE8FULLGAME00000",
+             Some(("ps4-hk", "HP0102-CUSA18072_00-RE8FULLGAME00000"))),
+            ("https://store.playstation.com/ja-jp/product/JP0102-CUSA18072_00-RE8FULLGAME00000",
+             Some(("ps4-jp", "JP0102-CUSA18072_00-RE8FULLGAME00000"))),
+            ("https://store.playstation.com/fr-fr/product/EP0102-CUSA18072_00-RE8FULLGAME00000",
+             None),
+            ("https://www.nintendo.com/games/detail/super-mario-maker-2-switch/",
+             Some(("switch-us", "super-mario-maker-2-switch"))),
+            ("https://www.epicgames.com/store/en-US/p/hitman-3",
+             Some(("epic-us", "hitman-3"))),
+            ("https://store.epicgames.com/en-US/p/hitman-3",
+             Some(("epic-us", "hitman-3"))),
+            ("https://store.steampowered.com/app/1145360/Hades/",
+             Some(("steam-us", "1145360"))),
+            ("https://store.steampowered.com/sub/371511/",
+             Some(("steam-us", "sub-371511"))),
+            ("https://store.steampowered.com/app/1145360/Hades/?cc=gb",
+             Some(("steam-uk", "1145360"))),
+            ("https://store.steampowered.com/sub/371511/?cc=jp&l=japanese",
+             Some(("steam-jp", "sub-371511"))),
+            ("https://store.steampowered.com/app/1145360/?cc=fr",
+             Some(("steam-eu", "1145360"))),
+            ("https://store.steampowered.com/app/1145360/?cc=br",
+             None),
+            ("https://store.steampowered.com/bundle/232/Supergiant_Games_Bundle/",
+             Some(("steam-us", "bundle-232"))),
+            ("https://store.steampowered.com/news/app/1145360",
+             None),
+            ("https://www.humblebundle.com/store/hades",
+             Some(("humble", "hades"))),
+            ("https://www.xbox.com/en-GB/games/store/forza-horizon-5-standard-edition/9NKX70BBCDRN",
+             Some(("xbox-uk", "9NKX70BBCDRN"))),
+            ("https://www.microsoft.com/ja-jp/p/forza-horizon-5/9nkx70bbcdrn",
+             Some(("xbox-jp", "9NKX70BBCDRN"))),
+            ("https://www.xbox.com/fr-FR/games/store/forza-horizon-5/9NKX70BBCDRN",
+             None),
+            ("https://example.com/dp/B07LBDVJKN", None),
+            ("not a url", None),
+        ];
+        for (url, expected) in cases
         {
-            "ps4-us" => Ok(Self::PlayStation(
-                playstation::PlayStation::new(playstation::Region::US))),
-            "ps4-hk" => Ok(Self::PlayStation(
-                playstation::PlayStation::new(playstation::Region::HK))),
-            "switch-us" => Ok(Self::Switch(
-                switch::Switch::new(switch::Region::US))),
-            "amazon-us" => Ok(Self::Amazon(amazon::Amazon::new())),
-            "epic-us" => Ok(Self::Epic(epic::Epic::new(epic::Region::US))),
-            _ => Err(rterr!("Invalid store: {}", store_name)),
+            let key = registry.parseURL(url);
+            assert_eq!(key.as_ref().map(|k| (k.store.as_str(), k.id.as_str())),
+                       expected, "{}", url);
         }
     }
 }
@@ -1,19 +1,71 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use scraper::{Html, Selector};
 use serde_json as json;
 
+use crate::data::ItemKey;
 use crate::error::Error;
-use crate::store::ItemInfo;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend, storeName};
 use crate::utils;
 
 pub enum Region
 {
     US,
     HK,
+    EU,
+    UK,
+    JP,
+}
+
+impl Region
+{
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
+    {
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            Some("hk") => Ok(Self::HK),
+            Some("eu") => Ok(Self::EU),
+            Some("uk") => Ok(Self::UK),
+            Some("jp") => Ok(Self::JP),
+            _ => Err(error!(NotFound, "Invalid PlayStation region: {:?}", code)),
+        }
+    }
+
+    /// The region code of the country in a store locale, e.g. “us”
+    /// for “en-us”. Germany is the “eu” region.
+    fn fromLocale(locale: &str) -> Option<String>
+    {
+        let country = locale.rsplit('-').next()?.to_lowercase();
+        Some(match country.as_str()
+        {
+            "gb" => String::from("uk"),
+            "de" => String::from("eu"),
+            _ => country,
+        })
+    }
+}
+
+/// The data the store pages are rendered from, by cache key, e.g.
+/// “Product:UP0102-PPSA01556_00-VILLAGEFULLGAMEX”. Other objects
+/// refer to them with {"__ref": <key>}.
+type Cache = HashMap<String, json::Value>;
+
+/// A price of a product, from one of its purchase buttons.
+struct Offer
+{
+    /// Type of the button, e.g. “ADD_TO_CART” or “PREORDER”.
+    kind: String,
+    price: json::Value,
 }
 
 pub struct PlayStation
 {
-    region: &'static str,
+    locale: &'static str,
     name: &'static str,
+    currency: &'static str,
 }
 
 impl PlayStation
@@ -22,49 +74,351 @@ impl PlayStation
     {
         match region
         {
-            Region::US => Self{ region: "en/US", name: "ps4-us" },
-            Region::HK => Self{ region: "zh/HK", name: "ps4-hk" },
+            Region::US => Self{ locale: "en-us", name: "ps4-us",
+                                currency: "USD" },
+            Region::HK => Self{ locale: "zh-hant-hk", name: "ps4-hk",
+                                currency: "HKD" },
+            Region::EU => Self{ locale: "de-de", name: "ps4-eu",
+                                currency: "EUR" },
+            Region::UK => Self{ locale: "en-gb", name: "ps4-uk",
+                                currency: "GBP" },
+            Region::JP => Self{ locale: "ja-jp", name: "ps4-jp",
+                                currency: "JPY" },
         }
     }
 
-    fn dataURL(&self, id: &str) -> String
+    /// Product IDs look like “UP0102-PPSA01556_00-VILLAGEFULLGAMEX”.
+    /// Concepts, which group the editions of a game, have numeric IDs.
+    fn isConcept(id: &str) -> bool
     {
-        format!("https://store.playstation.com/valkyrie-api/{}/19/resolve/{}",
-                self.region, id)
+        !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
     }
 
-    pub async fn get(&self, id: &str) -> Result<ItemInfo, Error>
+    fn itemURL(&self, id: &str) -> String
     {
-        let url = self.dataURL(id);
-        let content = utils::get(&url).await?;
-        let data: json::Value = serde_json::from_str(&content).map_err(
-            |_| rterr!("Failed to parse JSON"))?;
+        let kind = if Self::isConcept(id) { "concept" } else { "product" };
+        format!("https://store.playstation.com/{}/{}/{}", self.locale, kind, id)
+    }
 
-        for item in data["included"].as_array().ok_or(
-            rterr!("Failed to get sub-item"))?
+    /// Collect the cache from the JSON embedded in a store page. It is
+    /// split among the “env:…” scripts, and in older pages is the
+    /// Apollo state in “__NEXT_DATA__”.
+    fn cache(content: &str) -> Cache
+    {
+        let document = Html::parse_document(content);
+        let sel = Selector::parse("script[type=\"application/json\"]").unwrap();
+        let mut cache = Cache::new();
+        for script in document.select(&sel)
         {
-            if let Some(game_type) = item["attributes"]["game-content-type"].as_str()
+            let data: json::Value = match serde_json::from_str(
+                &script.text().collect::<String>())
+            {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            for part in [&data["cache"], &data["props"]["apolloState"]]
             {
-                if game_type == "Full Game" || game_type == "PSN Game"
+                if let Some(objects) = part.as_object()
                 {
-                    let name = item["attributes"]["name"].as_str().ok_or(
-                        rterr!("Failed to get item name"))?;
-                    let price_obj = &item["attributes"]["skus"][0]["prices"]
-                        ["non-plus-user"]["actual-price"];
-                    let price = price_obj["value"].as_i64().ok_or(
-                        rterr!("Failed to get price"))?;
-                    let price_str = price_obj["display"].as_str().ok_or(
-                        rterr!("Failed to get price string"))?;
-                    let mut item = ItemInfo::new(self.name, id);
-                    item.name = name.to_owned();
-                    item.url = format!(
-                        "https://store.playstation.com/en-us/product/{}", id);
-                    item.price = price;
-                    item.price_str = price_str.to_owned();
-                    return Ok(item);
+                    cache.extend(objects.iter().map(
+                        |(k, v)| (k.clone(), v.clone())));
                 }
             }
         }
-        Err(rterr!("Failed to retrieve item info"))
+        cache
+    }
+
+    /// Follow {"__ref": <key>}. Anything else is returned as is.
+    fn resolve<'a>(cache: &'a Cache, value: &'a json::Value) -> &'a json::Value
+    {
+        match value["__ref"].as_str()
+        {
+            Some(key) => cache.get(key).unwrap_or(&json::Value::Null),
+            None => value,
+        }
+    }
+
+    /// The product with `id`, or the default product of the concept
+    /// with `id`.
+    fn findProduct<'a>(cache: &'a Cache, id: &str) -> Option<&'a json::Value>
+    {
+        if !Self::isConcept(id)
+        {
+            return cache.values().find(
+                |v| v["__typename"] == "Product" && v["id"] == id);
+        }
+        let concept = cache.values().find(
+            |v| v["__typename"] == "Concept" && v["id"] == id)?;
+        let product = Self::resolve(cache, &concept["defaultProduct"]);
+        if product.is_object()
+        {
+            return Some(product);
+        }
+        concept["products"].as_array()?.iter()
+            .map(|p| Self::resolve(cache, p)).find(|p| p.is_object())
+    }
+
+    /// The priced purchase buttons of a product.
+    fn offers(cache: &Cache, product: &json::Value) -> Vec<Offer>
+    {
+        product["webctas"].as_array().map(|ctas| ctas.iter().filter_map(|cta| {
+            let cta = Self::resolve(cache, cta);
+            let price = &cta["price"];
+            if price["discountedValue"].is_i64() || price["basePriceValue"].is_i64()
+            {
+                Some(Offer { kind: cta["type"].as_str().unwrap_or_default()
+                             .to_owned(),
+                             price: price.clone() })
+            }
+            else
+            {
+                None
+            }
+        }).collect()).unwrap_or_default()
+    }
+
+    /// Whether the price is only for PlayStation Plus members.
+    fn isMemberPrice(price: &json::Value) -> bool
+    {
+        price["isTiedToSubscription"].as_bool().unwrap_or(false) ||
+            price["serviceBranding"].as_array().is_some_and(
+                |b| b.iter().any(|s| s == "PS_PLUS"))
+    }
+
+    /// Extract the item from its product or concept page.
+    fn parse(&self, id: &str, content: &str) -> Result<ItemInfo, Error>
+    {
+        let cache = Self::cache(content);
+        if cache.is_empty()
+        {
+            return Err(error!(StoreChanged, "Failed to find store data"));
+        }
+        let product = Self::findProduct(&cache, id).ok_or_else(
+            || error!(NotFound, "Product {} not found", id))?;
+        let name = product["name"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get item name"))?;
+
+        let mut item = ItemInfo::new(self.name, id);
+        item.name = name.to_owned();
+        item.url = self.itemURL(id);
+        item.currency = self.currency.to_owned();
+
+        let offers = Self::offers(&cache, product);
+        let offer = match offers.iter().find(|o| !Self::isMemberPrice(&o.price))
+        {
+            Some(o) => o,
+            None =>
+            {
+                // Nothing to buy anymore
+                item.availability = Availability::Delisted;
+                return Ok(item);
+            },
+        };
+        let price = &offer.price;
+        let currency = price["currencyCode"].as_str().unwrap_or(self.currency);
+        let value = price["discountedValue"].as_i64()
+            .or_else(|| price["basePriceValue"].as_i64()).unwrap_or_default();
+        // The values are in the smallest unit of the currency.
+        let scale = if utils::currencyDecimals(currency) == 0 { 100 } else { 1 };
+        item.setPrice(&utils::Money::new(value * scale, currency));
+        if let Some(s) = price["discountedPrice"].as_str()
+            .or_else(|| price["basePrice"].as_str())
+        {
+            item.price_str = s.to_owned();
+        }
+        if let Some(base) = price["basePriceValue"].as_i64()
+        {
+            item.setRegularPrice(&utils::Money::new(base * scale, currency));
+            if item.discount_percent.is_some()
+            {
+                item.sale_end = Self::endTime(&price["endTime"]);
+            }
+        }
+        item.member_price = offers.iter().filter(|o| Self::isMemberPrice(&o.price))
+            .filter_map(|o| o.price["discountedValue"].as_i64())
+            .map(|v| v * scale).filter(|v| *v < item.price).min();
+        item.availability = if offer.kind == "PREORDER"
+        {
+            Availability::Preorder
+        }
+        else
+        {
+            Availability::InStock
+        };
+        Ok(item)
+    }
+
+    /// The end of a sale, in milliseconds since epoch, as a string or
+    /// a number.
+    fn endTime(value: &json::Value) -> Option<chrono::DateTime<chrono::Utc>>
+    {
+        let ms = value.as_i64().or_else(|| value.as_str()?.parse().ok())?;
+        chrono::DateTime::from_timestamp_millis(ms)
+    }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let content = http.get(&self.itemURL(id)).await?;
+        self.parse(id, &content)
+    }
+}
+
+/// The PlayStation store family, “ps4-*”. Despite the name, it has
+/// PS5 games and add-ons too.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "ps4" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        ["us", "hk", "eu", "uk", "jp"].iter().map(|r| r.to_string()).collect()
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        PlayStation::new(Region::fromCode(region)?).get(http, id).await
+    }
+
+    /// Recognize “store.playstation.com/<locale>/product/<id>” and
+    /// “store.playstation.com/<locale>/concept/<id>”. The region is
+    /// the country part of the locale, e.g. “en-us”.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        if host != "store.playstation.com" || segs.len() < 3 ||
+            (segs[1] != "product" && segs[1] != "concept")
+        {
+            return None;
+        }
+        let region = Region::fromLocale(&segs[0])?;
+        Some(ItemKey { store: storeName(self.name(), Some(&region)),
+                       id: segs[2].clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    const ID: &str = "UP9000-PPSA03016_00-MARVELSPIDERMAN2";
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let content = fixture("playstation/product-us.html");
+        let item = PlayStation::new(Region::US).parse(ID, &content)?;
+        assert_eq!(item.store, "ps4-us");
+        assert_eq!(item.name, "Marvel's Spider-Man 2");
+        assert_eq!(item.url, format!(
+            "https://store.playstation.com/en-us/product/{}", ID));
+        assert_eq!(item.price, 4899);
+        assert_eq!(item.price_str, "$48.99");
+        assert_eq!(item.currency, "USD");
+        assert_eq!(item.regular_price, Some(6999));
+        assert_eq!(item.discount_percent, Some(30));
+        assert_eq!(item.sale_end.map(|t| t.timestamp()), Some(1717199940));
+        assert_eq!(item.member_price, Some(4199));
+        assert_eq!(item.availability, Availability::InStock);
+
+        // Not in the page
+        let e = PlayStation::new(Region::US).parse(
+            "UP9000-PPSA03016_00-OTHERGAME0000000", &content).unwrap_err();
+        assert_eq!(e.kind(), "not_found");
+        let e = PlayStation::new(Region::US).parse(ID, "<html></html>")
+            .unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+        Ok(())
+    }
+
+    #[test]
+    fn concept_and_add_on() -> Result<(), Error>
+    {
+        let item = PlayStation::new(Region::JP).parse(
+            "10010783", &fixture("playstation/concept-jp.html"))?;
+        assert_eq!(item.store, "ps4-jp");
+        assert_eq!(item.name, "DEATH STRANDING 2: ON THE BEACH");
+        assert_eq!(item.url, "https://store.playstation.com/ja-jp/concept/10010783");
+        assert_eq!(item.price, 898000);
+        assert_eq!(item.price_str, "￥8,980");
+        assert_eq!(item.currency, "JPY");
+        assert_eq!(item.discount_percent, None);
+        assert_eq!(item.member_price, None);
+        assert_eq!(item.availability, Availability::Preorder);
+
+        let id = "EP0102-CUSA18097_00-H3SEVENSINSBUNDL";
+        let item = PlayStation::new(Region::UK).parse(
+            id, &fixture("playstation/addon-uk.html"))?;
+        assert_eq!(item.store, "ps4-uk");
+        assert_eq!(item.name, "HITMAN 3 - Seven Deadly Sins Collection");
+        assert_eq!(item.price, 2499);
+        assert_eq!(item.currency, "GBP");
+        assert_eq!(item.availability, Availability::InStock);
+        Ok(())
+    }
+
+    #[test]
+    fn delisted() -> Result<(), Error>
+    {
+        let content = fixture("playstation/product-us.html").replace(
+            "\"webctas\":[{\"__ref\":\"GameCTA:ADD_TO_CART:",
+            "\"webctas\":[],\"old\":[{\"__ref\":\"GameCTA:ADD_TO_CART:");
+        let item = PlayStation::new(Region::US).parse(ID, &content)?;
+        assert_eq!(item.availability, Availability::Delisted);
+        assert_eq!(item.price, 0);
+        assert_eq!(item.name, "Marvel's Spider-Man 2");
+
+        // A concept whose default product cannot be bought
+        let item = PlayStation::new(Region::JP).parse(
+            "10010783", &fixture("playstation/concept-jp.html")
+                .replace("\"defaultProduct\":{\"__ref\":\"Product:JP9000-PPSA09999_00-DS2STANDARD00000\"},", ""))?;
+        assert_eq!(item.availability, Availability::Delisted);
+        Ok(())
+    }
+
+    #[test]
+    fn get_price() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/en-us/product/UP9000-PPSA03016_00-MARVELSPIDERMAN2",
+                 fixture("playstation/product-us.html")),
+                ("/ja-jp/concept/10010783", fixture("playstation/concept-jp.html"))]);
+            let item = PlayStation::new(Region::US).get(&server.client(), ID)
+                .await?;
+            assert_eq!(item.price, 4899);
+            let item = PlayStation::new(Region::JP)
+                .get(&server.client(), "10010783").await?;
+            assert_eq!(item.price, 898000);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn parse_url()
+    {
+        let cases = [
+            ("https://store.playstation.com/en-us/product/UP9000-PPSA03016_00-MARVELSPIDERMAN2",
+             Some("ps4-us")),
+            ("https://store.playstation.com/de-de/concept/10002456", Some("ps4-eu")),
+            ("https://store.playstation.com/en-gb/product/EP0102-CUSA18097_00-H3SEVENSINSBUNDL",
+             Some("ps4-uk")),
+            ("https://store.playstation.com/ja-jp/concept/10010783", Some("ps4-jp")),
+            ("https://store.playstation.com/en-us/category/44d8bb20-653e-431e-8ad0-c0a365f68d2f",
+             None),
+        ];
+        for (url, store) in cases
+        {
+            assert_eq!(Backend.parseURL(url).map(|k| k.store).as_deref(), store,
+                       "{}", url);
+        }
     }
 }
@@ -0,0 +1,419 @@
+use async_trait::async_trait;
+use serde_json as json;
+
+use crate::data::ItemKey;
+use crate::error::Error;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend, storeName};
+use crate::utils;
+
+pub enum Region
+{
+    US,
+    CA,
+    EU,
+    UK,
+    JP,
+    AU,
+    HK,
+}
+
+impl Region
+{
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
+    {
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            Some("ca") => Ok(Self::CA),
+            Some("eu") => Ok(Self::EU),
+            Some("uk") => Ok(Self::UK),
+            Some("jp") => Ok(Self::JP),
+            Some("au") => Ok(Self::AU),
+            Some("hk") => Ok(Self::HK),
+            _ => Err(error!(NotFound, "Invalid Steam region: {:?}", code)),
+        }
+    }
+
+    /// The region code of a country code in the “cc” parameter of
+    /// store URLs, e.g. “gb”. The euro store serves the euro area.
+    fn fromCountry(cc: &str) -> Option<&'static str>
+    {
+        match cc.to_lowercase().as_str()
+        {
+            "us" => Some("us"),
+            "ca" => Some("ca"),
+            "at" | "be" | "bg" | "cy" | "de" | "ee" | "es" | "fi" | "fr" |
+            "gr" | "hr" | "ie" | "it" | "lt" | "lu" | "lv" | "mt" | "nl" |
+            "pt" | "si" | "sk" => Some("eu"),
+            "gb" | "uk" => Some("uk"),
+            "jp" => Some("jp"),
+            "au" => Some("au"),
+            "hk" => Some("hk"),
+            _ => None,
+        }
+    }
+}
+
+/// What an item ID refers to. An app is a game or a DLC, a sub is a
+/// package of apps, and a bundle is a discounted set of packages.
+#[derive(Debug, PartialEq)]
+enum Kind
+{
+    App,
+    Sub,
+    Bundle,
+}
+
+impl Kind
+{
+    /// Split an item ID into its kind and the Steam ID. Apps are
+    /// plain appids, e.g. “1145360”; the others are prefixed, e.g.
+    /// “sub-371511” and “bundle-232”.
+    fn split(id: &str) -> Result<(Self, &str), Error>
+    {
+        let (kind, steam_id) = match id.split_once('-')
+        {
+            Some(("sub", steam_id)) => (Self::Sub, steam_id),
+            Some(("bundle", steam_id)) => (Self::Bundle, steam_id),
+            Some(_) => return Err(error!(InvalidInput, "Invalid Steam ID: {}", id)),
+            None => (Self::App, id),
+        };
+        if steam_id.is_empty() || !steam_id.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(error!(InvalidInput, "Invalid Steam ID: {}", id));
+        }
+        Ok((kind, steam_id))
+    }
+
+    /// The path segment of the store page.
+    fn path(&self) -> &'static str
+    {
+        match self
+        {
+            Self::App => "app",
+            Self::Sub => "sub",
+            Self::Bundle => "bundle",
+        }
+    }
+}
+
+pub struct Steam
+{
+    cc: &'static str,
+    name: &'static str,
+    currency: &'static str,
+}
+
+impl Steam
+{
+    pub fn new(region: Region) -> Self
+    {
+        match region
+        {
+            Region::US => Self{ cc: "US", name: "steam-us", currency: "USD" },
+            Region::CA => Self{ cc: "CA", name: "steam-ca", currency: "CAD" },
+            Region::EU => Self{ cc: "DE", name: "steam-eu", currency: "EUR" },
+            Region::UK => Self{ cc: "GB", name: "steam-uk", currency: "GBP" },
+            Region::JP => Self{ cc: "JP", name: "steam-jp", currency: "JPY" },
+            Region::AU => Self{ cc: "AU", name: "steam-au", currency: "AUD" },
+            Region::HK => Self{ cc: "HK", name: "steam-hk", currency: "HKD" },
+        }
+    }
+
+    fn pageURL(&self, kind: &Kind, steam_id: &str) -> String
+    {
+        format!("https://store.steampowered.com/{}/{}/?cc={}", kind.path(),
+                steam_id, self.cc.to_lowercase())
+    }
+
+    /// Set the price of `item` from the final and initial prices of
+    /// Steam, which are always multiplied by 100, even in currencies
+    /// without minor units.
+    fn setPrices(item: &mut ItemInfo, price: &json::Value, currency: &str,
+                 final_key: &str, initial_key: &str) -> Result<(), Error>
+    {
+        let final_price = price[final_key].as_i64().ok_or(
+            error!(StoreChanged, "Failed to get price"))?;
+        item.setPrice(&utils::Money::new(final_price, currency));
+        if let Some(initial) = price[initial_key].as_i64()
+        {
+            item.setRegularPrice(&utils::Money::new(initial, currency));
+        }
+        Ok(())
+    }
+
+    /// The data of `steam_id` in the response of the app details or
+    /// the package details API, which are keyed by the ID.
+    fn details<'a>(data: &'a json::Value, steam_id: &str) ->
+        Result<&'a json::Value, Error>
+    {
+        let entry = &data[steam_id];
+        if entry.is_null()
+        {
+            return Err(error!(StoreChanged, "Failed to find details"));
+        }
+        if entry["success"].as_bool() != Some(true)
+        {
+            return Err(error!(NotFound, "Steam item {} not found", steam_id));
+        }
+        Ok(&entry["data"])
+    }
+
+    /// Extract the item from the response of the API of its kind.
+    fn parse(&self, id: &str, content: &str) -> Result<ItemInfo, Error>
+    {
+        let (kind, steam_id) = Kind::split(id)?;
+        let data: json::Value = serde_json::from_str(content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let mut item = ItemInfo::new(self.name, id);
+        item.url = self.pageURL(&kind, steam_id);
+        item.currency = self.currency.to_owned();
+
+        // A bundle is a list of bundles instead. It does not come
+        // with a currency or a release date.
+        let data = match kind
+        {
+            Kind::Bundle =>
+            {
+                let bundle = data.as_array().and_then(|b| b.first()).ok_or_else(
+                    || error!(NotFound, "Steam bundle {} not found", steam_id))?;
+                item.name = bundle["name"].as_str().ok_or(
+                    error!(StoreChanged, "Failed to extract name"))?.to_owned();
+                Self::setPrices(&mut item, bundle, self.currency, "final_price",
+                                "initial_price")?;
+                item.availability = Availability::InStock;
+                return Ok(item);
+            },
+            Kind::App | Kind::Sub => Self::details(&data, steam_id)?,
+        };
+        item.name = data["name"].as_str().ok_or(
+            error!(StoreChanged, "Failed to extract name"))?.to_owned();
+
+        let coming_soon = data["release_date"]["coming_soon"].as_bool()
+            .unwrap_or(false);
+        let price = match kind
+        {
+            Kind::Sub => &data["price"],
+            _ => &data["price_overview"],
+        };
+        if !price.is_null()
+        {
+            let currency = price["currency"].as_str().unwrap_or(self.currency);
+            Self::setPrices(&mut item, price, currency, "final", "initial")?;
+            item.availability = if coming_soon
+            {
+                Availability::Preorder
+            }
+            else
+            {
+                Availability::InStock
+            };
+        }
+        else if data["is_free"].as_bool() == Some(true)
+        {
+            item.setPrice(&utils::Money::new(0, self.currency));
+            item.availability = Availability::InStock;
+        }
+        else if coming_soon
+        {
+            // Not released and not open for preorder yet
+            item.availability = Availability::OutOfStock;
+        }
+        else
+        {
+            item.availability = Availability::Delisted;
+        }
+        Ok(item)
+    }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let (kind, steam_id) = Kind::split(id)?;
+        let req = match kind
+        {
+            Kind::App => http.request(
+                "https://store.steampowered.com/api/appdetails").query(
+                &[("appids", steam_id), ("cc", self.cc), ("l", "english")]),
+            Kind::Sub => http.request(
+                "https://store.steampowered.com/api/packagedetails").query(
+                &[("packageids", steam_id), ("cc", self.cc), ("l", "english")]),
+            Kind::Bundle => http.request(
+                "https://store.steampowered.com/actions/ajaxresolvebundles").query(
+                &[("bundleids", steam_id), ("cc", self.cc), ("l", "english")]),
+        };
+        let content = http.send(req).await?;
+        self.parse(id, &content)
+    }
+}
+
+/// The Steam store family, “steam-*”.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "steam" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        ["us", "ca", "eu", "uk", "jp", "au", "hk"].iter().map(|r| r.to_string())
+            .collect()
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Steam::new(Region::fromCode(region)?).get(http, id).await
+    }
+
+    /// Recognize “store.steampowered.com/app/<appid>”, and the same
+    /// with “sub” and “bundle”. The region is from the “cc” parameter,
+    /// as in the URLs of `Steam`. Without it, Steam picks the region by
+    /// where the visitor is, so the URL resolves to the US store.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        let cc = reqwest::Url::parse(url).ok()?.query_pairs()
+            .find(|(key, _)| key == "cc").map(|(_, value)| value.into_owned());
+        let region = match cc
+        {
+            Some(cc) => Region::fromCountry(&cc)?,
+            None => "us",
+        };
+        if host != "store.steampowered.com" || segs.len() < 2
+        {
+            return None;
+        }
+        let id = match segs[0].as_str()
+        {
+            "app" => segs[1].clone(),
+            "sub" | "bundle" => format!("{}-{}", segs[0], segs[1]),
+            _ => return None,
+        };
+        Kind::split(&id).ok()?;
+        Some(ItemKey { store: storeName(self.name(), Some(region)), id })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    #[test]
+    fn split_id() -> Result<(), Error>
+    {
+        assert_eq!(Kind::split("1145360")?, (Kind::App, "1145360"));
+        assert_eq!(Kind::split("sub-371511")?, (Kind::Sub, "371511"));
+        assert_eq!(Kind::split("bundle-232")?, (Kind::Bundle, "232"));
+        assert!(Kind::split("dlc-232").is_err());
+        assert!(Kind::split("sub-").is_err());
+        assert!(Kind::split("hades").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_app() -> Result<(), Error>
+    {
+        let steam = Steam::new(Region::US);
+        let item = steam.parse("1145360", &fixture("steam/app-1145360.json"))?;
+        assert_eq!(item.store, "steam-us");
+        assert_eq!(item.name, "Hades");
+        assert_eq!(item.price, 999);
+        assert_eq!(item.price_str, "$9.99");
+        assert_eq!(item.currency, "USD");
+        assert_eq!(item.regular_price, Some(2499));
+        assert_eq!(item.discount_percent, Some(60));
+        assert_eq!(item.availability, Availability::InStock);
+        assert_eq!(item.url, "https://store.steampowered.com/app/1145360/?cc=us");
+
+        // Zero-decimal prices are still multiplied by 100.
+        let item = Steam::new(Region::JP).parse(
+            "1145360", &fixture("steam/app-jp.json"))?;
+        assert_eq!(item.price, 205000);
+        assert_eq!(item.currency, "JPY");
+        assert_eq!(item.discount_percent, None);
+
+        let e = steam.parse("9999999", &fixture("steam/not-found.json"))
+            .unwrap_err();
+        assert_eq!(e.kind(), "not_found");
+        let e = steam.parse("1145360", "{}").unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_app_without_price() -> Result<(), Error>
+    {
+        let steam = Steam::new(Region::US);
+        let app = |data: &str| format!(
+            r#"{{"10": {{"success": true, "data": {}}}}}"#, data);
+        let item = steam.parse("10", &app(
+            r#"{"name": "Free", "is_free": true}"#))?;
+        assert_eq!(item.price, 0);
+        assert_eq!(item.price_str, "$0.00");
+        assert_eq!(item.availability, Availability::InStock);
+        let item = steam.parse("10", &app(
+            r#"{"name": "Soon", "is_free": false,
+                "release_date": {"coming_soon": true}}"#))?;
+        assert_eq!(item.availability, Availability::OutOfStock);
+        let item = steam.parse("10", &app(
+            r#"{"name": "Soon", "is_free": false,
+                "price_overview": {"currency": "USD", "initial": 2999, "final": 2999},
+                "release_date": {"coming_soon": true}}"#))?;
+        assert_eq!(item.price, 2999);
+        assert_eq!(item.availability, Availability::Preorder);
+        let item = steam.parse("10", &app(r#"{"name": "Gone", "is_free": false}"#))?;
+        assert_eq!(item.availability, Availability::Delisted);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_sub_and_bundle() -> Result<(), Error>
+    {
+        let steam = Steam::new(Region::US);
+        let item = steam.parse("sub-371511", &fixture("steam/sub-371511.json"))?;
+        assert_eq!(item.name, "Hades");
+        assert_eq!(item.price, 2499);
+        assert_eq!(item.discount_percent, None);
+        assert_eq!(item.availability, Availability::InStock);
+        assert_eq!(item.url, "https://store.steampowered.com/sub/371511/?cc=us");
+
+        let item = steam.parse("bundle-232", &fixture("steam/bundle-232.json"))?;
+        assert_eq!(item.name, "Supergiant Games Bundle");
+        assert_eq!(item.price, 4267);
+        assert_eq!(item.regular_price, Some(7097));
+        assert_eq!(item.discount_percent, Some(40));
+        assert_eq!(item.url, "https://store.steampowered.com/bundle/232/?cc=us");
+        assert_eq!(steam.parse("bundle-232", "[]").unwrap_err().kind(),
+                   "not_found");
+        Ok(())
+    }
+
+    #[test]
+    fn get_price() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/api/appdetails", fixture("steam/app-1145360.json")),
+                ("/api/packagedetails", fixture("steam/sub-371511.json")),
+                ("/actions/ajaxresolvebundles", fixture("steam/bundle-232.json"))]);
+            let steam = Steam::new(Region::UK);
+            let item = steam.get(&server.client(), "1145360").await?;
+            assert_eq!(item.name, "Hades");
+            assert_eq!(item.store, "steam-uk");
+            steam.get(&server.client(), "sub-371511").await?;
+            steam.get(&server.client(), "bundle-232").await?;
+            assert_eq!(server.requests(), vec![
+                "/api/appdetails?appids=1145360&cc=GB&l=english",
+                "/api/packagedetails?packageids=371511&cc=GB&l=english",
+                "/actions/ajaxresolvebundles?bundleids=232&cc=GB&l=english"]);
+            Ok(())
+        })
+    }
+}
@@ -1,13 +1,89 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use scraper::{Html, Selector};
 use serde_json as json;
-use reqwest;
 
+use crate::data::ItemKey;
 use crate::error::Error;
-use crate::store::ItemInfo;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend, storeName};
 use crate::utils;
 
 pub enum Region
 {
     US,
+    CA,
+    EU,
+    UK,
+    JP,
+    AU,
+    HK,
+}
+
+impl Region
+{
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
+    {
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            Some("ca") => Ok(Self::CA),
+            Some("eu") => Ok(Self::EU),
+            Some("uk") => Ok(Self::UK),
+            Some("jp") => Ok(Self::JP),
+            Some("au") => Ok(Self::AU),
+            Some("hk") => Ok(Self::HK),
+            _ => Err(error!(NotFound, "Invalid Switch region: {:?}", code)),
+        }
+    }
+}
+
+/// The public search credentials of the Nintendo of America catalogue,
+/// which is hosted by Algolia.
+const AMERICAS_SEARCH_APP_ID: &str = "U3B6GR4UA3";
+const AMERICAS_SEARCH_KEY: &str = "a29c6927638bfd8cee23993e51e721c9";
+
+/// A price in the price API, with its string.
+type Price = (utils::Money, String);
+
+/// What the price API says about a title.
+struct PriceInfo
+{
+    /// None if the title is not sold.
+    regular: Option<Price>,
+    /// The sale price and when the sale ends, if there is a sale.
+    discount: Option<(Price, Option<DateTime<Utc>>)>,
+    availability: Availability,
+}
+
+/// Where the title and the nsuid of a game are looked up. Each kind
+/// of catalogue has its own kind of item ID.
+enum Catalogue
+{
+    /// The search API of Nintendo of America in an index, by the slug
+    /// in the URL of the game page, e.g. “hollow-knight-switch”. The
+    /// game pages are on nintendo.com under the prefix.
+    Americas { index: &'static str, prefix: &'static str },
+    /// The search API of Nintendo of Europe in a language, by the
+    /// number at the end of the URL of the game page, e.g. “1232004”
+    /// from “…/Hollow-Knight-1232004.html”. The game pages are on the
+    /// site.
+    Europe { lang: &'static str, site: &'static str },
+    /// The search API of Nintendo Japan, by nsuid.
+    Japan,
+    /// The title pages of the eShop, by nsuid.
+    EShop,
+}
+
+/// A game found in a catalogue.
+#[derive(Debug, PartialEq)]
+struct Title
+{
+    name: String,
+    /// The ID in the price API.
+    nsuid: String,
+    /// The page of the game.
+    url: String,
 }
 
 pub struct Switch
@@ -15,6 +91,8 @@ pub struct Switch
     country: &'static str,
     lang: &'static str,
     name: &'static str,
+    currency: &'static str,
+    catalogue: Catalogue,
 }
 
 impl Switch
@@ -23,62 +101,502 @@ impl Switch
     {
         match region
         {
-            Region::US => Self{ country: "US", lang: "en", name: "switch-us" },
+            Region::US => Self{ country: "US", lang: "en", name: "switch-us",
+                                currency: "USD", catalogue: Catalogue::Americas {
+                                    index: "store_game_en_us", prefix: "" } },
+            Region::CA => Self{ country: "CA", lang: "en", name: "switch-ca",
+                                currency: "CAD", catalogue: Catalogue::Americas {
+                                    index: "store_game_en_ca", prefix: "/en-ca" } },
+            Region::EU => Self{ country: "DE", lang: "de", name: "switch-eu",
+                                currency: "EUR", catalogue: Catalogue::Europe {
+                                    lang: "de", site: "https://www.nintendo.de" } },
+            Region::UK => Self{ country: "GB", lang: "en", name: "switch-uk",
+                                currency: "GBP", catalogue: Catalogue::Europe {
+                                    lang: "en", site: "https://www.nintendo.co.uk" } },
+            Region::JP => Self{ country: "JP", lang: "ja", name: "switch-jp",
+                                currency: "JPY", catalogue: Catalogue::Japan },
+            Region::AU => Self{ country: "AU", lang: "en", name: "switch-au",
+                                currency: "AUD", catalogue: Catalogue::EShop },
+            Region::HK => Self{ country: "HK", lang: "zh", name: "switch-hk",
+                                currency: "HKD", catalogue: Catalogue::EShop },
         }
     }
 
-    fn dataURL(&self, id: &str) -> String
+    /// Find the game with slug `id` in the response of the Nintendo
+    /// of America search API.
+    fn parseAmericasSearch(id: &str, prefix: &str, content: &str) ->
+        Result<Title, Error>
     {
-        format!("https://www.nintendo.com/games/detail/{}/", id)
+        let data: json::Value = serde_json::from_str(content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let hits = data["hits"].as_array().ok_or(
+            error!(StoreChanged, "Failed to get search results"))?;
+        let hit = hits.iter().find(|h| h["urlKey"].as_str() == Some(id))
+            .ok_or_else(|| error!(NotFound, "Switch game {} not found", id))?;
+        let name = hit["title"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get title"))?;
+        let nsuid = hit["nsuid"].as_str().ok_or_else(
+            || error!(NotFound, "Switch game {} is not sold in the eShop", id))?;
+        Ok(Title { name: name.to_owned(), nsuid: nsuid.to_owned(),
+                   url: format!("https://www.nintendo.com{}/games/detail/{}/",
+                                prefix, id) })
     }
 
-    fn getTitleID(content: &str) -> Result<(&str, &str), Error>
+    /// Find the game with “fs_id” `id` in the response of the
+    /// Nintendo of Europe search API.
+    fn parseEuropeSearch(id: &str, site: &str, content: &str) ->
+        Result<Title, Error>
     {
-        // Get position of ‘{’.
-        let (store_data_str, _, _) = utils::findSubStr(
-            content, "Object.freeze({", "});").ok_or(
-            rterr!("Failed to find store data"))?;
+        let data: json::Value = serde_json::from_str(content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let docs = data["response"]["docs"].as_array().ok_or(
+            error!(StoreChanged, "Failed to get search results"))?;
+        let doc = docs.iter().find(|d| d["fs_id"].as_str() == Some(id))
+            .ok_or_else(|| error!(NotFound, "Switch game {} not found", id))?;
+        let name = doc["title"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get title"))?;
+        let nsuid = doc["nsuid_txt"][0].as_str().ok_or_else(
+            || error!(NotFound, "Switch game {} is not sold in the eShop", id))?;
+        let path = doc["url"].as_str().unwrap_or_default();
+        Ok(Title { name: name.to_owned(), nsuid: nsuid.to_owned(),
+                   url: format!("{}{}", site, path) })
+    }
 
-        let (title, _, _) = utils::findSubStr(
-            store_data_str, "title: \"", "\",")
-            .ok_or(rterr!("Failed to find store data"))?;
+    /// Find the game with nsuid `id` in the response of the Nintendo
+    /// Japan search API.
+    fn parseJapanSearch(id: &str, content: &str) -> Result<Title, Error>
+    {
+        let data: json::Value = serde_json::from_str(content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let items = data["result"]["items"].as_array().ok_or(
+            error!(StoreChanged, "Failed to get search results"))?;
+        let item = items.iter().find(|i| i["nsuid"].as_str() == Some(id))
+            .ok_or_else(|| error!(NotFound, "Switch game {} not found", id))?;
+        let name = item["title"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get title"))?;
+        Ok(Title { name: name.to_owned(), nsuid: id.to_owned(),
+                   url: format!("https://store-jp.nintendo.com/list/software/{}.html",
+                                id) })
+    }
 
-        // let title: String = serde_json::from_str(
-        //     &store_data_str[title_json_begin..title_json_end+1]).map_err(
-        //     |_| rterr!("Failed to parse title JSON"))?;
+    /// The name of the game in an eShop title page.
+    fn parseEShopPage(content: &str) -> Result<String, Error>
+    {
+        let document = Html::parse_document(content);
+        let sel = Selector::parse("meta[property=\"og:title\"]").unwrap();
+        document.select(&sel).next().and_then(|m| m.value().attr("content"))
+            .map(|s| s.trim().to_owned()).filter(|s| !s.is_empty())
+            .ok_or(error!(StoreChanged, "Failed to find title"))
+    }
 
-        let (real_id, _, _) = utils::findSubStr(store_data_str, "nsuid: \"", "\",")
-            .ok_or(rterr!( "Failed to find real ID"))?;
-        Ok((title, real_id))
+    /// Look up the game with `id` in the catalogue of the region.
+    async fn lookup(&self, http: &http::StoreClient, id: &str) ->
+        Result<Title, Error>
+    {
+        match &self.catalogue
+        {
+            Catalogue::Americas { index, prefix } =>
+            {
+                let req = http.request(&format!(
+                    "https://{}-dsn.algolia.net/1/indexes/{}",
+                    AMERICAS_SEARCH_APP_ID.to_lowercase(), index))
+                    .query(&[("query", id.replace('-', " ").as_str()),
+                             ("x-algolia-application-id", AMERICAS_SEARCH_APP_ID),
+                             ("x-algolia-api-key", AMERICAS_SEARCH_KEY)]);
+                Self::parseAmericasSearch(id, prefix, &http.send(req).await?)
+            },
+            Catalogue::Europe { lang, site } =>
+            {
+                let req = http.request(&format!(
+                    "https://searching.nintendo-europe.com/{}/select", lang))
+                    .query(&[("q", "*"), ("fq", &format!("fs_id:{}", id)),
+                             ("wt", "json")]);
+                Self::parseEuropeSearch(id, site, &http.send(req).await?)
+            },
+            Catalogue::Japan =>
+            {
+                let req = http.request(
+                    "https://search.nintendo.jp/nintendo_soft/search.json")
+                    .query(&[("q", id)]);
+                Self::parseJapanSearch(id, &http.send(req).await?)
+            },
+            Catalogue::EShop =>
+            {
+                let url = format!("https://ec.nintendo.com/{}/{}/titles/{}",
+                                  self.country, self.lang, id);
+                let name = Self::parseEShopPage(&http.get(&url).await?)?;
+                Ok(Title { name, nsuid: id.to_owned(), url })
+            },
+        }
     }
 
-    pub async fn get(&self, id: &str) -> Result<ItemInfo, Error>
+    fn availability(sales_status: &str) -> Availability
     {
-        // Get title and real ID.
-        let store_url = self.dataURL(id);
-        let content = utils::get(&store_url).await?;
+        match sales_status
+        {
+            "onsale" => Availability::InStock,
+            "pre_order" | "preorder" => Availability::Preorder,
+            "sales_termination" | "not_found" => Availability::Delisted,
+            _ => Availability::Unknown,
+        }
+    }
 
-        let (title, real_id) = Self::getTitleID(&content)?;
+    /// A price object of the price API, e.g. “regular_price”.
+    fn parsePriceObject(&self, price_data: &json::Value) -> Result<Price, Error>
+    {
+        let price_raw = price_data["raw_value"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get price"))?;
+        let currency = price_data["currency"].as_str().unwrap_or(self.currency);
+        let price = utils::Money::fromDecimal(price_raw, currency)?;
+        let price_str = price_data["amount"].as_str().ok_or(
+            error!(StoreChanged, "Failed to get price string"))?;
+        Ok((price, price_str.to_owned()))
+    }
 
-        // Get price.
-        let price_url = "https://api.ec.nintendo.com/v1/price";
-        let req = reqwest::Client::new().get(price_url).query(
-            &[("country", self.country), ("lang", self.lang), ("ids", real_id)]);
-        let content = utils::queryRequest(req).await?;
-        let data: json::Value = serde_json::from_str(&content).map_err(
-            |_| rterr!("Failed to parse JSON"))?;
+    /// Extract the prices and the availability from the response of
+    /// the price API. A discount that is not in effect at `now` is
+    /// ignored.
+    fn parsePrice(&self, content: &str, now: DateTime<Utc>) ->
+        Result<PriceInfo, Error>
+    {
+        let data: json::Value = serde_json::from_str(content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
 
+        let availability = Self::availability(
+            data["prices"][0]["sales_status"].as_str().unwrap_or_default());
         let price_data = &data["prices"][0]["regular_price"];
-        let price_raw = price_data["raw_value"].as_str().ok_or(
-            rterr!("Failed to get price"))?;
-        let price = utils::parsePrice(&price_raw)?;
-        let price_str = price_data["amount"].as_str().ok_or(
-            rterr!("Failed to get price string"))?;
+        if price_data.is_null() && availability.cannotOrder()
+        {
+            return Ok(PriceInfo { regular: None, discount: None,
+                                  availability });
+        }
+        let regular = self.parsePriceObject(price_data)?;
+
+        let discount_data = &data["prices"][0]["discount_price"];
+        let time = |key: &str| discount_data[key].as_str().and_then(
+            |t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc));
+        let start = time("start_datetime");
+        let end = time("end_datetime");
+        let discount = if discount_data.is_null() ||
+            start.is_some_and(|t| t > now) || end.is_some_and(|t| t <= now)
+        {
+            None
+        }
+        else
+        {
+            Some((self.parsePriceObject(discount_data)?, end))
+        };
+        Ok(PriceInfo { regular: Some(regular), discount, availability })
+    }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let title = self.lookup(http, id).await?;
+
+        let req = http.request("https://api.ec.nintendo.com/v1/price").query(
+            &[("country", self.country), ("lang", self.lang),
+              ("ids", &title.nsuid)]);
+        let content = http.send(req).await?;
+        let info = self.parsePrice(&content, Utc::now())?;
+
         let mut item = ItemInfo::new(self.name, id);
-        item.name = title.to_owned();
-        item.url = store_url;
-        item.price = price;
-        item.price_str = price_str.to_owned();
+        item.name = title.name;
+        item.url = title.url;
+        item.currency = self.currency.to_owned();
+        if let Some((regular, regular_str)) = info.regular
+        {
+            match info.discount
+            {
+                Some(((price, price_str), end)) =>
+                {
+                    item.setPrice(&price);
+                    item.price_str = price_str;
+                    item.sale_end = end;
+                },
+                None =>
+                {
+                    item.setPrice(&regular);
+                    item.price_str = regular_str;
+                },
+            }
+            item.setRegularPrice(&regular);
+        }
+        item.availability = info.availability;
         Ok(item)
     }
 }
+
+/// The Nintendo eShop family, “switch-*”.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "switch" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        ["us", "ca", "eu", "uk", "jp", "au", "hk"].iter().map(|r| r.to_string())
+            .collect()
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Switch::new(Region::fromCode(region)?).get(http, id).await
+    }
+
+    /// Recognize
+    ///
+    /// * “nintendo.com/games/detail/<id>” and
+    ///   “nintendo.com/en-ca/games/detail/<id>”,
+    /// * “nintendo.co.uk/…/<name>-<id>.html” and the same at
+    ///   “nintendo.de”,
+    /// * “ec.nintendo.com/<country>/<lang>/titles/<nsuid>”, and
+    /// * “store-jp.nintendo.com/list/software/<nsuid>.html”.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        let (region, id) = match host.as_str()
+        {
+            "nintendo.com" =>
+            {
+                let (region, rest) = match segs.first()?.as_str()
+                {
+                    "en-ca" => ("ca", &segs[1..]),
+                    _ => ("us", &segs[..]),
+                };
+                if rest.len() < 3 || rest[0] != "games" || rest[1] != "detail"
+                {
+                    return None;
+                }
+                (region.to_owned(), rest[2].clone())
+            },
+            "nintendo.co.uk" | "nintendo.de" =>
+            {
+                let page = segs.last()?.strip_suffix(".html")?;
+                let id = page.rsplit('-').next()?;
+                if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit())
+                {
+                    return None;
+                }
+                let region = if host == "nintendo.de" { "eu" } else { "uk" };
+                (region.to_owned(), id.to_owned())
+            },
+            "ec.nintendo.com" =>
+            {
+                if segs.len() < 4 || segs[2] != "titles"
+                {
+                    return None;
+                }
+                let region = segs[0].to_lowercase();
+                if !["jp", "au", "hk"].contains(&region.as_str())
+                {
+                    return None;
+                }
+                (region, segs[3].clone())
+            },
+            "store-jp.nintendo.com" =>
+            {
+                if segs.len() < 3 || segs[1] != "software"
+                {
+                    return None;
+                }
+                (String::from("jp"), segs[2].strip_suffix(".html")?.to_owned())
+            },
+            _ => return None,
+        };
+        Some(ItemKey { store: storeName(self.name(), Some(&region)), id })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let s = Switch::new(Region::US);
+        let now = Utc::now();
+        let info = s.parsePrice(&fixture("switch/price.json"), now)?;
+        assert_eq!(info.regular, Some((utils::Money::new(1499, "USD"),
+                                       String::from("$14.99"))));
+        assert!(info.discount.is_none());
+        assert_eq!(info.availability, Availability::InStock);
+        assert_eq!(s.parsePrice(r#"{"prices": []}"#, now).err()
+                   .map(|e| e.kind()), Some("store_changed"));
+
+        let info = s.parsePrice(r#"{"prices": [{"title_id": 70010000003208,
+            "sales_status": "sales_termination"}]}"#, now)?;
+        assert_eq!(info.regular, None);
+        assert_eq!(info.availability, Availability::Delisted);
+        Ok(())
+    }
+
+    #[test]
+    fn sale() -> Result<(), Error>
+    {
+        let s = Switch::new(Region::US);
+        let content = fixture("switch/price-sale.json");
+        let during: DateTime<Utc> = "2024-05-10T00:00:00Z".parse().unwrap();
+        let info = s.parsePrice(&content, during)?;
+        let ((price, price_str), end) = info.discount.unwrap();
+        assert_eq!(price, utils::Money::new(749, "USD"));
+        assert_eq!(price_str, "$7.49");
+        assert_eq!(end, Some("2024-05-15T06:59:59Z".parse().unwrap()));
+        assert_eq!(info.regular.unwrap().0, utils::Money::new(1499, "USD"));
+
+        for outside in ["2024-04-30T00:00:00Z", "2024-05-16T00:00:00Z"]
+        {
+            let info = s.parsePrice(&content, outside.parse().unwrap())?;
+            assert!(info.discount.is_none(), "{}", outside);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn catalogues() -> Result<(), Error>
+    {
+        let search = fixture("switch/americas-search.json");
+        let title = Switch::parseAmericasSearch(
+            "hollow-knight-switch", "/en-ca", &search)?;
+        assert_eq!(title, Title {
+            name: String::from("Hollow Knight"),
+            nsuid: String::from("70010000003208"),
+            url: String::from("https://www.nintendo.com/en-ca/games/detail/\
+                               hollow-knight-switch/"),
+        });
+        assert_eq!(Switch::parseAmericasSearch("hollow-knight", "", &search)
+                   .unwrap_err().kind(), "not_found");
+        assert_eq!(Switch::parseAmericasSearch("hollow-knight-switch", "", "{}")
+                   .unwrap_err().kind(), "store_changed");
+
+        let title = Switch::parseEuropeSearch(
+            "1232004", "https://www.nintendo.co.uk",
+            &fixture("switch/europe-search.json"))?;
+        assert_eq!(title, Title {
+            name: String::from("Hollow Knight"),
+            nsuid: String::from("70010000003207"),
+            url: String::from("https://www.nintendo.co.uk/Games/\
+                               Nintendo-Switch-download-software/\
+                               Hollow-Knight-1232004.html"),
+        });
+        assert_eq!(Switch::parseEuropeSearch(
+            "1", "", &fixture("switch/europe-search.json")).unwrap_err().kind(),
+                   "not_found");
+
+        let title = Switch::parseJapanSearch(
+            "70010000003211", &fixture("switch/japan-search.json"))?;
+        assert_eq!(title.name, "Hollow Knight（ホロウナイト）");
+        assert_eq!(title.url,
+                   "https://store-jp.nintendo.com/list/software/70010000003211.html");
+
+        assert_eq!(Switch::parseEShopPage(&fixture("switch/eshop-hk.html"))?,
+                   "Hollow Knight");
+        assert_eq!(Switch::parseEShopPage("<html></html>").unwrap_err().kind(),
+                   "store_changed");
+        Ok(())
+    }
+
+    #[test]
+    fn get_price_regions() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/en/select", fixture("switch/europe-search.json")),
+                ("/v1/price", fixture("switch/price-uk.json"))]);
+            let item = Switch::new(Region::UK).get(&server.client(), "1232004")
+                .await?;
+            assert_eq!(item.store, "switch-uk");
+            assert_eq!(item.name, "Hollow Knight");
+            assert_eq!(item.price, 1199);
+            assert_eq!(item.currency, "GBP");
+            assert_eq!(server.requests(), vec![
+                "/en/select?q=*&fq=fs_id%3A1232004&wt=json",
+                "/v1/price?country=GB&lang=en&ids=70010000003207"]);
+
+            let server = MockServer::start(vec![
+                ("/nintendo_soft/search.json", fixture("switch/japan-search.json")),
+                ("/v1/price", fixture("switch/price-jp.json"))]);
+            let item = Switch::new(Region::JP)
+                .get(&server.client(), "70010000003211").await?;
+            assert_eq!(item.price, 150000);
+            assert_eq!(item.price_str, "1,500円");
+            assert_eq!(server.requests()[1],
+                       "/v1/price?country=JP&lang=ja&ids=70010000003211");
+
+            let server = MockServer::start(vec![
+                ("/HK/zh/titles/70010000012345", fixture("switch/eshop-hk.html")),
+                ("/v1/price", fixture("switch/price-hk.json"))]);
+            let item = Switch::new(Region::HK)
+                .get(&server.client(), "70010000012345").await?;
+            assert_eq!(item.name, "Hollow Knight");
+            assert_eq!(item.price, 11800);
+            assert_eq!(item.currency, "HKD");
+            assert_eq!(item.url, "https://ec.nintendo.com/HK/zh/titles/70010000012345");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn parse_url()
+    {
+        let cases = [
+            ("https://www.nintendo.com/games/detail/hollow-knight-switch/",
+             Some(("switch-us", "hollow-knight-switch"))),
+            ("https://www.nintendo.com/en-ca/games/detail/hollow-knight-switch/",
+             Some(("switch-ca", "hollow-knight-switch"))),
+            ("https://www.nintendo.co.uk/Games/Nintendo-Switch-download-software/\
+              Hollow-Knight-1232004.html", Some(("switch-uk", "1232004"))),
+            ("https://www.nintendo.de/Spiele/Nintendo-Switch-Download-Software/\
+              Hollow-Knight-1232004.html", Some(("switch-eu", "1232004"))),
+            ("https://ec.nintendo.com/AU/en/titles/70010000003207",
+             Some(("switch-au", "70010000003207"))),
+            ("https://store-jp.nintendo.com/list/software/70010000003211.html",
+             Some(("switch-jp", "70010000003211"))),
+            ("https://ec.nintendo.com/US/en/titles/70010000003208", None),
+            ("https://www.nintendo.co.uk/Games/Games-347085.html#news", Some(
+                ("switch-uk", "347085"))),
+            ("https://www.nintendo.co.uk/Hardware/index.html", None),
+        ];
+        for (url, expected) in cases
+        {
+            let key = Backend.parseURL(url);
+            assert_eq!(key.as_ref().map(|k| (k.store.as_str(), k.id.as_str())),
+                       expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn get_price() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/1/indexes/store_game_en_us",
+                 fixture("switch/americas-search.json")),
+                ("/v1/price", fixture("switch/price.json"))]);
+            let item = Switch::new(Region::US)
+                .get(&server.client(), "hollow-knight-switch").await?;
+            assert_eq!(item.name, "Hollow Knight");
+            assert_eq!(item.url,
+                       "https://www.nintendo.com/games/detail/hollow-knight-switch/");
+            assert_eq!(item.price, 1499);
+            assert_eq!(item.regular_price, Some(1499));
+            assert_eq!(item.discount_percent, None);
+            assert_eq!(server.requests(), vec![
+                format!("/1/indexes/store_game_en_us?query=hollow+knight+switch&\
+                         x-algolia-application-id={}&x-algolia-api-key={}",
+                        AMERICAS_SEARCH_APP_ID, AMERICAS_SEARCH_KEY),
+                String::from("/v1/price?country=US&lang=en&ids=70010000003208")]);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,341 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Utc};
+use serde_json as json;
+
+use crate::data::ItemKey;
+use crate::error::Error;
+use crate::http;
+use crate::store::{Availability, ItemInfo, StoreBackend, storeName};
+use crate::utils;
+
+pub enum Region
+{
+    US,
+    CA,
+    EU,
+    UK,
+    JP,
+    AU,
+    HK,
+}
+
+impl Region
+{
+    fn fromCode(code: Option<&str>) -> Result<Self, Error>
+    {
+        match code
+        {
+            Some("us") => Ok(Self::US),
+            Some("ca") => Ok(Self::CA),
+            Some("eu") => Ok(Self::EU),
+            Some("uk") => Ok(Self::UK),
+            Some("jp") => Ok(Self::JP),
+            Some("au") => Ok(Self::AU),
+            Some("hk") => Ok(Self::HK),
+            _ => Err(error!(NotFound, "Invalid Xbox region: {:?}", code)),
+        }
+    }
+
+    /// The region code of a locale in store URLs, e.g. “en-gb”.
+    fn fromLocale(locale: &str) -> Option<&'static str>
+    {
+        match locale.to_lowercase().as_str()
+        {
+            "en-us" => Some("us"),
+            "en-ca" => Some("ca"),
+            "de-de" => Some("eu"),
+            "en-gb" => Some("uk"),
+            "ja-jp" => Some("jp"),
+            "en-au" => Some("au"),
+            "zh-hk" => Some("hk"),
+            _ => None,
+        }
+    }
+}
+
+pub struct Xbox
+{
+    market: &'static str,
+    locale: &'static str,
+    name: &'static str,
+    currency: &'static str,
+}
+
+impl Xbox
+{
+    pub fn new(region: Region) -> Self
+    {
+        match region
+        {
+            Region::US => Self{ market: "US", locale: "en-us", name: "xbox-us",
+                                currency: "USD" },
+            Region::CA => Self{ market: "CA", locale: "en-ca", name: "xbox-ca",
+                                currency: "CAD" },
+            Region::EU => Self{ market: "DE", locale: "de-de", name: "xbox-eu",
+                                currency: "EUR" },
+            Region::UK => Self{ market: "GB", locale: "en-gb", name: "xbox-uk",
+                                currency: "GBP" },
+            Region::JP => Self{ market: "JP", locale: "ja-jp", name: "xbox-jp",
+                                currency: "JPY" },
+            Region::AU => Self{ market: "AU", locale: "en-au", name: "xbox-au",
+                                currency: "AUD" },
+            Region::HK => Self{ market: "HK", locale: "zh-hk", name: "xbox-hk",
+                                currency: "HKD" },
+        }
+    }
+
+    fn pageURL(&self, id: &str) -> String
+    {
+        format!("https://www.xbox.com/{}/games/store/-/{}", self.locale, id)
+    }
+
+    /// A time in the conditions of an availability. The far future
+    /// means never.
+    fn time(value: &json::Value) -> Option<DateTime<Utc>>
+    {
+        let t = DateTime::parse_from_rfc3339(value.as_str()?).ok()?
+            .with_timezone(&Utc);
+        if t.year() >= 9000 { None } else { Some(t) }
+    }
+
+    /// The availabilities of the main SKU that can be purchased at
+    /// `now`.
+    fn purchasable(product: &json::Value, now: DateTime<Utc>) ->
+        Vec<&json::Value>
+    {
+        product["DisplaySkuAvailabilities"][0]["Availabilities"].as_array()
+            .map(|a| a.iter().filter(|a| {
+                let conditions = &a["Conditions"];
+                a["Actions"].as_array().is_some_and(
+                    |actions| actions.iter().any(|x| x == "Purchase")) &&
+                    Self::time(&conditions["StartDate"]).is_none_or(|t| t <= now) &&
+                    Self::time(&conditions["EndDate"]).is_none_or(|t| t > now)
+            }).collect()).unwrap_or_default()
+    }
+
+    /// Extract the item from the response of the display catalog API.
+    /// Availabilities that require a subscription, e.g. Game Pass,
+    /// give the member price.
+    fn parse(&self, id: &str, content: &str, now: DateTime<Utc>) ->
+        Result<ItemInfo, Error>
+    {
+        let data: json::Value = serde_json::from_str(content).map_err(
+            |e| error_from!(Parse, e, "Failed to parse JSON"))?;
+        let products = data["Products"].as_array().ok_or(
+            error!(StoreChanged, "Failed to get products"))?;
+        let product = products.first().ok_or_else(
+            || error!(NotFound, "Xbox product {} not found", id))?;
+        let name = product["LocalizedProperties"][0]["ProductTitle"].as_str()
+            .ok_or(error!(StoreChanged, "Failed to extract name"))?;
+
+        let mut item = ItemInfo::new(self.name, id);
+        item.name = name.to_owned();
+        item.url = self.pageURL(id);
+        item.currency = self.currency.to_owned();
+
+        let availabilities = Self::purchasable(product, now);
+        let (member, regular): (Vec<_>, Vec<_>) = availabilities.into_iter()
+            .partition(|a| a["RemediationRequired"].as_bool() == Some(true));
+        // The price and the regular price of an availability
+        let price_of = |a: &json::Value| ->
+            Result<(utils::Money, utils::Money), Error>
+        {
+            let price = &a["OrderManagementData"]["Price"];
+            let currency = price["CurrencyCode"].as_str().unwrap_or(self.currency);
+            Ok((utils::Money::fromJSON(&price["ListPrice"], currency)?,
+                utils::Money::fromJSON(&price["MSRP"], currency)?))
+        };
+        let mut cheapest: Option<(&json::Value, utils::Money, utils::Money)> = None;
+        for a in regular
+        {
+            let (price, msrp) = price_of(a)?;
+            if cheapest.as_ref().is_none_or(|(_, p, _)| price.amount < p.amount)
+            {
+                cheapest = Some((a, price, msrp));
+            }
+        }
+        let (offer, price, msrp) = match cheapest
+        {
+            Some(c) => c,
+            None =>
+            {
+                // Nothing to buy anymore
+                item.availability = Availability::Delisted;
+                return Ok(item);
+            },
+        };
+        item.setPrice(&price);
+        item.setRegularPrice(&msrp);
+        if item.discount_percent.is_some()
+        {
+            item.sale_end = Self::time(&offer["Conditions"]["EndDate"]);
+        }
+        let mut member_price = None;
+        for a in member
+        {
+            let (p, _) = price_of(a)?;
+            if p.amount < item.price && member_price.is_none_or(|m| p.amount < m)
+            {
+                member_price = Some(p.amount);
+            }
+        }
+        item.member_price = member_price;
+        let release = Self::time(
+            &product["MarketProperties"][0]["OriginalReleaseDate"]);
+        item.availability = if release.is_some_and(|t| t > now)
+        {
+            Availability::Preorder
+        }
+        else
+        {
+            Availability::InStock
+        };
+        Ok(item)
+    }
+
+    pub async fn get(&self, http: &http::StoreClient, id: &str) ->
+        Result<ItemInfo, Error>
+    {
+        let req = http.request(
+            "https://displaycatalog.mp.microsoft.com/v7.0/products").query(
+            &[("bigIds", id), ("market", self.market), ("languages", self.locale)]);
+        let content = http.send(req).await?;
+        self.parse(id, &content, Utc::now())
+    }
+}
+
+/// The Microsoft Store family for Xbox and PC games, “xbox-*”.
+pub struct Backend;
+
+#[async_trait]
+impl StoreBackend for Backend
+{
+    fn name(&self) -> &str { "xbox" }
+
+    fn regions(&self) -> Vec<String>
+    {
+        ["us", "ca", "eu", "uk", "jp", "au", "hk"].iter().map(|r| r.to_string())
+            .collect()
+    }
+
+    async fn get(&self, http: &http::StoreClient, region: Option<&str>,
+                 id: &str) -> Result<ItemInfo, Error>
+    {
+        Xbox::new(Region::fromCode(region)?).get(http, id).await
+    }
+
+    /// Recognize “xbox.com/<locale>/games/store/<name>/<id>” and
+    /// “microsoft.com/<locale>/p/<name>/<id>”. The product ID is
+    /// always 12 letters and digits.
+    fn parseURL(&self, url: &str) -> Option<ItemKey>
+    {
+        let (host, segs) = utils::splitURL(url)?;
+        let is_page = match host.as_str()
+        {
+            "xbox.com" => segs.len() >= 5 && segs[1] == "games" &&
+                segs[2] == "store",
+            "microsoft.com" => segs.len() >= 4 && segs[1] == "p",
+            _ => false,
+        };
+        if !is_page
+        {
+            return None;
+        }
+        let id = segs.last()?.to_uppercase();
+        if id.len() != 12 || !id.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        let region = Region::fromLocale(&segs[0])?;
+        Some(ItemKey { store: storeName(self.name(), Some(region)), id })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use tokio;
+    use crate::testing::{fixture, MockServer};
+
+    fn at(s: &str) -> DateTime<Utc>
+    {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse() -> Result<(), Error>
+    {
+        let xbox = Xbox::new(Region::US);
+        let content = fixture("xbox/products-9nkx70bbcdrn.json");
+        let item = xbox.parse("9NKX70BBCDRN", &content,
+                              at("2024-05-15T00:00:00Z"))?;
+        assert_eq!(item.store, "xbox-us");
+        assert_eq!(item.name, "Forza Horizon 5 Standard Edition");
+        assert_eq!(item.price, 2999);
+        assert_eq!(item.price_str, "$29.99");
+        assert_eq!(item.regular_price, Some(5999));
+        assert_eq!(item.discount_percent, Some(50));
+        assert_eq!(item.sale_end, Some(at("2024-05-21T09:59:59.9999999Z")));
+        assert_eq!(item.member_price, Some(2699));
+        assert_eq!(item.availability, Availability::InStock);
+        assert_eq!(item.url, "https://www.xbox.com/en-us/games/store/-/9NKX70BBCDRN");
+
+        // After the sale, back to the base price
+        let item = xbox.parse("9NKX70BBCDRN", &content,
+                              at("2024-06-01T00:00:00Z"))?;
+        assert_eq!(item.availability, Availability::InStock);
+        assert_eq!(item.price, 5999);
+        assert_eq!(item.regular_price, Some(5999));
+        assert_eq!(item.discount_percent, None);
+        assert_eq!(item.sale_end, None);
+        assert_eq!(item.member_price, None);
+
+        // Nothing to purchase
+        let item = xbox.parse("9NKX70BBCDRN", &content.replace("\"Purchase\", ", ""),
+                              at("2024-06-01T00:00:00Z"))?;
+        assert_eq!(item.availability, Availability::Delisted);
+
+        let item = Xbox::new(Region::JP).parse(
+            "9NKX70BBCDRN", &fixture("xbox/products-jp.json"),
+            at("2024-05-15T00:00:00Z"))?;
+        assert_eq!(item.price, 770000);
+        assert_eq!(item.currency, "JPY");
+        assert_eq!(item.discount_percent, None);
+        assert_eq!(item.sale_end, None);
+
+        let e = xbox.parse("9NKX70BBCDRN", r#"{"Products": []}"#, Utc::now())
+            .unwrap_err();
+        assert_eq!(e.kind(), "not_found");
+        let e = xbox.parse("9NKX70BBCDRN", "{}", Utc::now()).unwrap_err();
+        assert_eq!(e.kind(), "store_changed");
+        Ok(())
+    }
+
+    #[test]
+    fn preorder() -> Result<(), Error>
+    {
+        let content = fixture("xbox/products-9nkx70bbcdrn.json");
+        let item = Xbox::new(Region::US).parse(
+            "9NKX70BBCDRN", &content.replace("2021-11-09", "2024-11-09"),
+            at("2024-05-15T00:00:00Z"))?;
+        assert_eq!(item.availability, Availability::Preorder);
+        Ok(())
+    }
+
+    #[test]
+    fn get_price() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = MockServer::start(vec![
+                ("/v7.0/products", fixture("xbox/products-jp.json"))]);
+            let item = Xbox::new(Region::JP).get(&server.client(), "9NKX70BBCDRN")
+                .await?;
+            assert_eq!(item.store, "xbox-jp");
+            assert_eq!(server.requests(), vec![
+                "/v7.0/products?bigIds=9NKX70BBCDRN&market=JP&languages=ja-jp"]);
+            Ok(())
+        })
+    }
+}
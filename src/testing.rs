@@ -0,0 +1,87 @@
+// Helpers for testing the stores without the network.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use warp::Filter;
+
+use crate::config::{HttpParams, StoreHttpParams};
+use crate::http;
+
+/// Content of a file in “tests/fixtures”.
+pub fn fixture(name: &str) -> String
+{
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter().collect();
+    std::fs::read_to_string(&path).unwrap_or_else(
+        |e| panic!("Failed to read fixture {}: {}", path.to_string_lossy(), e))
+}
+
+/// A local HTTP server that serves fixed responses by path, and 404
+/// for anything else.
+pub struct MockServer
+{
+    origin: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer
+{
+    /// Serve each body in `routes` at its path, e.g. “/dp/B08164VTWH”.
+    /// The query is not matched. Must be called within a Tokio
+    /// runtime.
+    pub fn start(routes: Vec<(&str, String)>) -> Self
+    {
+        let routes: Vec<(String, String)> = routes.into_iter()
+            .map(|(path, body)| (path.to_owned(), body)).collect();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        let route = warp::path::full()
+            .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+            .map(move |path: warp::path::FullPath, query: String| {
+                let path = path.as_str();
+                received.lock().unwrap().push(if query.is_empty()
+                {
+                    path.to_owned()
+                }
+                else
+                {
+                    format!("{}?{}", path, query)
+                });
+                match routes.iter().find(|(p, _)| p == path)
+                {
+                    Some((_, body)) => warp::reply::with_status(
+                        body.clone(), warp::http::StatusCode::OK),
+                    None => warp::reply::with_status(
+                        String::new(), warp::http::StatusCode::NOT_FOUND),
+                }
+            });
+        let (addr, server) = warp::serve(route)
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        Self { origin: format!("http://{}", addr), requests }
+    }
+
+    /// The URL of `path` on this server, e.g. “/hook”.
+    pub fn url(&self, path: &str) -> String
+    {
+        format!("{}{}", self.origin, path)
+    }
+
+    /// A client that sends all requests to this server.
+    pub fn client(&self) -> http::StoreClient
+    {
+        let mut params = HttpParams::default();
+        params.stores.insert(String::from("mock"), StoreHttpParams {
+            base_url: Some(self.origin.clone()),
+            ..StoreHttpParams::default()
+        });
+        Arc::new(http::Client::new(&params).unwrap()).forStore("mock", "mock")
+    }
+
+    /// Paths and queries of the requests received so far.
+    pub fn requests(&self) -> Vec<String>
+    {
+        self.requests.lock().unwrap().clone()
+    }
+}
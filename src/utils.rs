@@ -1,24 +1,107 @@
+use std::fmt;
+use std::time::Duration;
+
 use reqwest;
 use chrono;
+use log::warn;
 
 use crate::error::Error;
 
-const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:80.0) Gecko/20100101 Firefox/80.0";
+/// How many times to retry a failed request.
+const MAX_RETRIES: u32 = 3;
+/// Wait this long before the 1st retry, doubling for each retry after.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+/// Whether a request that failed with `status` is worth retrying.
+fn isTransientStatus(status: reqwest::StatusCode) -> bool
+{
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS ||
+        status.is_server_error()
+}
 
-pub async fn get(url: &str) -> Result<String, Error>
+/// Send the request and return the response body. Connection
+/// failures, timeouts, HTTP 429 and 5xx responses are retried up to
+/// `retries` times with exponential backoff. If the server sends a
+/// Retry-After in seconds, that is used instead. With `connect_only`,
+/// only failures to connect are retried, as the server may have acted
+/// on a request it answered slowly or with an error.
+async fn sendWithRetry(req: reqwest::RequestBuilder, retries: u32,
+                       base_delay: Duration, connect_only: bool) ->
+    Result<String, Error>
 {
-    let client = reqwest::Client::new();
-    client.get(url).header("User-Agent", USER_AGENT)
-        .send().await.map_err(|_| rterr!("Failed to get {}", url))?
-        .text().await.map_err(
-            |_| rterr!("Failed to retrieve content from {}", url))
+    let mut attempt = 0;
+    loop
+    {
+        let this_req = req.try_clone().ok_or_else(
+            || rterr!("Request cannot be retried"))?;
+        let mut delay = base_delay * 2u32.pow(attempt);
+        let error = match this_req.send().await
+        {
+            Ok(res) if res.status().is_success() =>
+            {
+                match res.text().await
+                {
+                    Ok(text) => return Ok(text),
+                    Err(e) => error_from!(
+                        Network, e, "Failed to retrieve content"),
+                }
+            },
+            Ok(res) =>
+            {
+                let err = if res.status() == reqwest::StatusCode::NOT_FOUND
+                {
+                    error!(NotFound, "Failed to get {}: HTTP {}", res.url(),
+                           res.status())
+                }
+                else
+                {
+                    error!(Network, "Failed to get {}: HTTP {}", res.url(),
+                           res.status())
+                };
+                if connect_only || !isTransientStatus(res.status())
+                {
+                    return Err(err);
+                }
+                if let Some(secs) = res.headers().get("Retry-After")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                {
+                    delay = Duration::from_secs(secs);
+                }
+                err
+            },
+            Err(e) =>
+            {
+                let retry = !connect_only || e.is_connect();
+                let err = error_from!(Network, e, "Failed to send request");
+                if !retry
+                {
+                    return Err(err);
+                }
+                err
+            },
+        };
+        if attempt >= retries
+        {
+            return Err(error);
+        }
+        attempt += 1;
+        warn!("{}. Retrying in {} seconds...", error, delay.as_secs_f32());
+        tokio::time::sleep(delay).await;
+    }
 }
 
+/// Send the request and return the response body, with retries.
 pub async fn queryRequest(req: reqwest::RequestBuilder) -> Result<String, Error>
 {
-    req.header("User-Agent", USER_AGENT)
-        .send().await.map_err(|_| rterr!("Failed to query"))?
-        .text().await.map_err(|_| rterr!("Failed to retrieve content"))
+    sendWithRetry(req, MAX_RETRIES, RETRY_BASE_DELAY, false).await
+}
+
+/// Send a request that should not be repeated, e.g. a POST, and
+/// return the response body. Only failures to connect are retried.
+pub async fn postRequest(req: reqwest::RequestBuilder) -> Result<String, Error>
+{
+    sendWithRetry(req, MAX_RETRIES, RETRY_BASE_DELAY, true).await
 }
 
 pub fn findSubStr<'a>(s: &'a str, begin: &'a str, end: &'a str)
@@ -45,24 +128,561 @@ pub fn findSubStr<'a>(s: &'a str, begin: &'a str, end: &'a str)
     Some((&s[start..stop], start, stop))
 }
 
-/// “39.99” -> 3999, “15” -> 1500
-pub fn parsePrice(price_raw: &str) -> Result<i64, Error>
+/// Split a URL into its host (without “www.”) and non-empty path
+/// segments. Return None if `url` is not a URL.
+pub fn splitURL(url: &str) -> Option<(String, Vec<String>)>
+{
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").unwrap_or(host).to_lowercase();
+    let segs = url.path_segments()
+        .map(|segs| segs.filter(|s| !s.is_empty()).map(|s| s.to_owned())
+             .collect())
+        .unwrap_or_default();
+    Some((host, segs))
+}
+
+/// Currencies without minor units. Their prices are still stored
+/// multiplied by 100, like all other prices.
+const ZERO_DECIMAL_CURRENCIES: &[&str] = &["JPY", "KRW", "VND", "CLP", "ISK"];
+
+/// Number of digits after the decimal point in prices of an ISO 4217
+/// currency.
+pub fn currencyDecimals(currency: &str) -> usize
+{
+    if ZERO_DECIMAL_CURRENCIES.contains(&currency) { 0 } else { 2 }
+}
+
+/// The symbol to show before a price, e.g. “HK$”. Currencies without
+/// a well-known symbol use the code followed by a space.
+pub fn currencySymbol(currency: &str) -> String
+{
+    match currency
+    {
+        "USD" => "$",
+        "CAD" => "CA$",
+        "AUD" => "A$",
+        "HKD" => "HK$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" => "¥",
+        "CNY" => "CN¥",
+        "KRW" => "₩",
+        "INR" => "₹",
+        "BRL" => "R$",
+        _ => return format!("{} ", currency),
+    }.to_owned()
+}
+
+/// Parse a price as displayed in a store of any locale, and return
+/// price * 100. The text may have a currency symbol or code, spaces,
+/// and thousands separators, and the decimal point may be a comma.
+/// For example “$1,299.99”, “1.299,99 €”, “HK$ 78.00”, and “¥1,500”.
+///
+/// A separator followed by exactly 3 digits is taken as a thousands
+/// separator, unless the other kind of separator comes before it.
+fn parseLocalPrice(raw: &str, currency: &str) -> Result<i64, Error>
+{
+    let is_sep = |c: char| c == '.' || c == ',';
+    let s: String = raw.chars().filter(|c| c.is_ascii_digit() || is_sep(*c))
+        .collect();
+    // Separators in symbols (“Rs.”) or after the number (“12,-”)
+    let s = s.trim_matches(is_sep);
+    if s.is_empty()
+    {
+        return Err(error!(Parse, "Failed to parse price: {}", raw));
+    }
+
+    let (int_part, frac_part) = match s.rfind(is_sep)
+    {
+        None => (s, ""),
+        Some(idx) =>
+        {
+            let sep = &s[idx..idx+1];
+            let other = if sep == "." { ',' } else { '.' };
+            let frac = &s[idx+1..];
+            let is_decimal = if s[..idx].contains(other)
+            {
+                true
+            }
+            else if s.matches(sep).count() > 1
+            {
+                false
+            }
+            else if currencyDecimals(currency) == 0
+            {
+                // “¥1,500.00” but not “¥1,500”
+                frac.chars().all(|c| c == '0') && frac.len() != 3
+            }
+            else
+            {
+                frac.len() != 3
+            };
+            if is_decimal { (&s[..idx], frac) } else { (s, "") }
+        },
+    };
+    if frac_part.len() > 2
+    {
+        return Err(error!(Parse, "Failed to parse price: {}", raw));
+    }
+    let digits: String = int_part.chars().filter(|c| c.is_ascii_digit())
+        .collect();
+    let int: i64 = digits.parse().map_err(
+        |e| error_from!(Parse, e, "Failed to parse price: {}", raw))?;
+    let frac: i64 = if frac_part.is_empty()
+    {
+        0
+    }
+    else
+    {
+        format!("{:0<2}", frac_part).parse().map_err(
+            |e| error_from!(Parse, e, "Failed to parse price: {}", raw))?
+    };
+    int.checked_mul(100).and_then(|v| v.checked_add(frac)).ok_or_else(
+        || error!(Parse, "Price out of range: {}", raw))
+}
+
+/// Format price * 100 for display, e.g. 129999 in USD -> “$1,299.99”.
+pub fn formatPrice(price: i64, currency: &str) -> String
 {
-    if price_raw.find(".").is_none()
+    let sign = if price < 0 { "-" } else { "" };
+    let price = price.abs();
+    let int = (price / 100).to_string();
+    let mut grouped = String::new();
+    for (i, c) in int.chars().enumerate()
     {
-        price_raw.parse::<i64>().map_err(
-            |_| rterr!("Failed to parse price: {}", price_raw))
-            .map(|x| x * 100)
+        if i > 0 && (int.len() - i).is_multiple_of(3)
+        {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    if currencyDecimals(currency) == 0
+    {
+        format!("{}{}{}", sign, currencySymbol(currency), grouped)
     }
     else
     {
-        price_raw.replace(".", "").parse().map_err(
-            |_| rterr!("Failed to parse price: {}", price_raw))
+        format!("{}{}{}.{:02}", sign, currencySymbol(currency), grouped,
+                price % 100)
     }
 }
 
+/// An exact amount of money. Like everywhere else, the amount is
+/// price * 100 in an integer, so no floating point is involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Money
+{
+    /// Price * 100
+    pub amount: i64,
+    /// ISO 4217 code, e.g. “USD”.
+    pub currency: String,
+}
+
+#[allow(dead_code)]
+impl Money
+{
+    pub fn new(amount: i64, currency: &str) -> Self
+    {
+        Self { amount, currency: currency.to_owned() }
+    }
+
+    /// Parse a price as displayed in a store of any locale, e.g.
+    /// “$1,299.99” or “1.299,99 €”.
+    pub fn parse(raw: &str, currency: &str) -> Result<Self, Error>
+    {
+        Ok(Self::new(parseLocalPrice(raw, currency)?, currency))
+    }
+
+    /// Parse a plain decimal number, as found in JSON and APIs, e.g.
+    /// “39.9” or “1299.9900”. Digits beyond cents must be zeros.
+    pub fn fromDecimal(s: &str, currency: &str) -> Result<Self, Error>
+    {
+        let err = || error!(Parse, "Invalid decimal price: {}", s);
+        let (negative, digits) = match s.trim().strip_prefix('-')
+        {
+            Some(rest) => (true, rest),
+            None => (false, s.trim()),
+        };
+        let (int_part, frac_part) = digits.split_once('.')
+            .unwrap_or((digits, ""));
+        let frac_part = frac_part.trim_end_matches('0');
+        if (int_part.is_empty() && frac_part.is_empty()) ||
+            !int_part.chars().all(|c| c.is_ascii_digit()) ||
+            !frac_part.chars().all(|c| c.is_ascii_digit()) ||
+            frac_part.len() > 2
+        {
+            return Err(err());
+        }
+        let int: i64 = if int_part.is_empty()
+        {
+            0
+        }
+        else
+        {
+            int_part.parse().map_err(
+                |e| error_from!(Parse, e, "Invalid decimal price: {}", s))?
+        };
+        let frac: i64 = format!("{:0<2}", frac_part).parse().map_err(
+            |e| error_from!(Parse, e, "Invalid decimal price: {}", s))?;
+        let amount = int.checked_mul(100).and_then(|a| a.checked_add(frac))
+            .ok_or_else(err)?;
+        Ok(Self::new(if negative { -amount } else { amount }, currency))
+    }
+
+    /// A price in JSON, either a number like 39.9, or a string that
+    /// is parsed with `parse()`.
+    pub fn fromJSON(value: &serde_json::Value, currency: &str) ->
+        Result<Self, Error>
+    {
+        match value
+        {
+            // The text of the number is exact, even if its f64 value
+            // is not.
+            serde_json::Value::Number(n) =>
+                Self::fromDecimal(&n.to_string(), currency),
+            serde_json::Value::String(s) => Self::parse(s, currency),
+            _ => Err(error!(Parse, "Invalid price in JSON: {}", value)),
+        }
+    }
+
+    fn checkCurrency(&self, other: &Money) -> Result<(), Error>
+    {
+        if self.currency == other.currency
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(rterr!("Currency mismatch: {} and {}", self.currency,
+                       other.currency))
+        }
+    }
+
+    fn withAmount(&self, amount: Option<i64>) -> Result<Money, Error>
+    {
+        let amount = amount.ok_or_else(
+            || rterr!("Amount out of range in {}", self.currency))?;
+        Ok(Self::new(amount, &self.currency))
+    }
+
+    pub fn plus(&self, other: &Money) -> Result<Money, Error>
+    {
+        self.checkCurrency(other)?;
+        self.withAmount(self.amount.checked_add(other.amount))
+    }
+
+    pub fn minus(&self, other: &Money) -> Result<Money, Error>
+    {
+        self.checkCurrency(other)?;
+        self.withAmount(self.amount.checked_sub(other.amount))
+    }
+
+    pub fn times(&self, n: i64) -> Result<Money, Error>
+    {
+        self.withAmount(self.amount.checked_mul(n))
+    }
+}
+
+impl fmt::Display for Money
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", formatPrice(self.amount, &self.currency))
+    }
+}
+
+/// “90” -> 90 seconds. Also accepts suffixes “s”, “m”, “h”, and “d”,
+/// e.g. “30m”. The duration must be positive, and its seconds must
+/// fit in an i64, which is how they are stored.
+pub fn parseDuration(s: &str) -> Result<Duration, Error>
+{
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit())
+    {
+        Some(idx) => (&s[..idx], &s[idx..]),
+        None => (s, "s"),
+    };
+    let factor = match unit
+    {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(error!(InvalidInput, "Invalid duration: {}", s)),
+    };
+    let num: u64 = num.parse().map_err(
+        |e| error_from!(InvalidInput, e, "Invalid duration: {}", s))?;
+    let secs = num.checked_mul(factor).filter(|&n| n <= i64::MAX as u64)
+        .ok_or_else(|| error!(InvalidInput, "Duration too long: {}", s))?;
+    if secs == 0
+    {
+        return Err(error!(InvalidInput, "Duration must be positive: {}", s));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
 pub fn timestampToUtcTime(ts: i64) -> chrono::DateTime<chrono::Utc>
 {
-    chrono::DateTime::<chrono::Utc>::from_utc(
-        chrono::NaiveDateTime::from_timestamp(ts, 0), chrono::Utc)
+    chrono::DateTime::<chrono::Utc>::from_timestamp(ts, 0)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use warp::Filter;
+
+    /// Serve `statuses` in turn, and 200 after that. Return the URL
+    /// and the number of requests received so far.
+    async fn serveStatuses(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>)
+    {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let route = warp::any().map(move || {
+            let i = counter.fetch_add(1, Ordering::SeqCst);
+            let status = statuses.get(i).cloned().unwrap_or(200);
+            warp::reply::with_status(
+                format!("response {}", i),
+                warp::http::StatusCode::from_u16(status).unwrap())
+        });
+        let (addr, server) = warp::serve(route)
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/", addr), count)
+    }
+
+    fn request(url: &str) -> reqwest::RequestBuilder
+    {
+        reqwest::Client::new().get(url)
+    }
+
+    #[test]
+    fn retry_transient_errors() -> Result<(), Error>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, count) = serveStatuses(vec![503, 429]).await;
+            let body = sendWithRetry(request(&url), 3,
+                                     Duration::from_millis(10), false).await?;
+            assert_eq!(body, "response 2");
+            assert_eq!(count.load(Ordering::SeqCst), 3);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn give_up_after_retries()
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, count) = serveStatuses(vec![500, 500, 500]).await;
+            let result = sendWithRetry(request(&url), 2,
+                                       Duration::from_millis(10), false).await;
+            assert!(result.is_err());
+            assert_eq!(count.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn no_retry_on_client_error()
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, count) = serveStatuses(vec![404]).await;
+            let result = sendWithRetry(request(&url), 3,
+                                       Duration::from_millis(10), false).await;
+            assert!(result.is_err());
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn no_retry_on_response_if_connect_only()
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (url, count) = serveStatuses(vec![503]).await;
+            let result = sendWithRetry(request(&url), 3,
+                                       Duration::from_millis(10), true).await;
+            assert!(result.is_err());
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+
+            // Nothing listens on the port of a dropped listener.
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            drop(listener);
+            let result = sendWithRetry(request(&format!("http://{}/", addr)), 1,
+                                       Duration::from_millis(10), true).await;
+            assert_eq!(result.unwrap_err().kind(), "network");
+        });
+    }
+
+    #[test]
+    fn parse_local_price() -> Result<(), Error>
+    {
+        let cases = [
+            ("$12.99", "USD", 1299),
+            ("$1,299.99", "USD", 129999),
+            ("$1,299", "USD", 129900),
+            ("$5", "USD", 500),
+            ("39.9", "USD", 3990),
+            ("HK$ 78.00", "HKD", 7800),
+            ("HK$1,234.50", "HKD", 123450),
+            ("12,99 €", "EUR", 1299),
+            ("1.299,99 €", "EUR", 129999),
+            ("1.299 €", "EUR", 129900),
+            ("12,- €", "EUR", 1200),
+            ("1 299,00 kr", "SEK", 129900),
+            ("1\u{a0}299,00\u{a0}€", "EUR", 129900),
+            ("CHF 1'299.50", "CHF", 129950),
+            ("R$ 1.299,90", "BRL", 129990),
+            ("Rs. 1,299", "INR", 129900),
+            ("¥1,500", "JPY", 150000),
+            ("¥1.500", "JPY", 150000),
+            ("¥1500", "JPY", 150000),
+            ("￥1,500.00", "JPY", 150000),
+            ("₩12,000", "KRW", 1200000),
+            ("1,234,567.89", "USD", 123456789),
+            ("1.234.567", "EUR", 123456700),
+            ("$59.99", "USD", 5999),
+            ("$0.99", "USD", 99),
+            ("US$19.99", "USD", 1999),
+            ("USD 19.99", "USD", 1999),
+            ("$ 1,299.00", "USD", 129900),
+            ("Price: $24.99", "USD", 2499),
+            ("£7.49", "GBP", 749),
+            ("€ 69,99", "EUR", 6999),
+            ("69,99€", "EUR", 6999),
+            ("EUR 1.049,00", "EUR", 104900),
+            ("1'049.00 CHF", "CHF", 104900),
+            ("CA$ 79.99", "CAD", 7999),
+            ("A$99.95", "AUD", 9995),
+            ("NZ$ 1,099.99", "NZD", 109999),
+            ("HK$468", "HKD", 46800),
+            ("CN¥ 298.00", "CNY", 29800),
+            ("₹ 3,999", "INR", 399900),
+            ("₹1,29,999.00", "INR", 12999900),
+            ("1 999 ₽", "RUB", 199900),
+            ("1.999,00 zł", "PLN", 199900),
+            ("kr 499,-", "NOK", 49900),
+            ("R$ 299,90", "BRL", 29990),
+            ("¥ 6,578", "JPY", 657800),
+            ("6578円", "JPY", 657800),
+            ("₩ 64,800", "KRW", 6480000),
+        ];
+        for (raw, currency, expected) in cases
+        {
+            assert_eq!(parseLocalPrice(raw, currency)?, expected, "{}", raw);
+        }
+        assert!(parseLocalPrice("Free", "USD").is_err());
+        assert!(parseLocalPrice("", "USD").is_err());
+        assert!(parseLocalPrice("1.2345", "USD").is_err());
+        // Fits in i64, but not when multiplied by 100
+        assert!(parseLocalPrice("$999,999,999,999,999,999", "USD").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn money_from_decimal() -> Result<(), Error>
+    {
+        let cases = [
+            ("39.9", 3990),
+            ("39.90", 3990),
+            ("39", 3900),
+            ("0.99", 99),
+            (".5", 50),
+            ("19.99", 1999),
+            ("1299.9900", 129999),
+            ("-2.5", -250),
+            (" 7 ", 700),
+            ("0", 0),
+        ];
+        for (raw, expected) in cases
+        {
+            assert_eq!(Money::fromDecimal(raw, "USD")?.amount, expected, "{}", raw);
+        }
+        for raw in ["", ".", "1.999", "1,299.99", "$5", "1e3", "--1",
+                    "99999999999999999999"]
+        {
+            assert!(Money::fromDecimal(raw, "USD").is_err(), "{}", raw);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn money_from_json() -> Result<(), Error>
+    {
+        let value: serde_json::Value = serde_json::from_str(
+            r#"[19.99, 0.29, 1150, "$1,299.99", null]"#).unwrap();
+        // 19.99 * 100.0 is 1998.9999999999998.
+        assert_eq!(Money::fromJSON(&value[0], "USD")?, Money::new(1999, "USD"));
+        assert_eq!(Money::fromJSON(&value[1], "USD")?.amount, 29);
+        assert_eq!(Money::fromJSON(&value[2], "JPY")?.amount, 115000);
+        assert_eq!(Money::fromJSON(&value[3], "USD")?.amount, 129999);
+        assert!(Money::fromJSON(&value[4], "USD").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn money_arithmetic() -> Result<(), Error>
+    {
+        let a = Money::new(1999, "USD");
+        let b = Money::new(1, "USD");
+        assert_eq!(a.plus(&b)?, Money::new(2000, "USD"));
+        assert_eq!(b.minus(&a)?, Money::new(-1998, "USD"));
+        assert_eq!(a.times(3)?, Money::new(5997, "USD"));
+        let max = Money::new(i64::MAX, "USD");
+        assert!(max.plus(&a).is_err());
+        assert!(Money::new(i64::MIN, "USD").minus(&a).is_err());
+        assert!(max.times(2).is_err());
+        assert!(a.plus(&Money::new(1999, "EUR")).is_err());
+        assert!(a.minus(&Money::new(1999, "EUR")).is_err());
+        // Cents add up exactly, unlike 0.1 + 0.2 in floats.
+        let sum = Money::fromDecimal("0.1", "USD")?
+            .plus(&Money::fromDecimal("0.2", "USD")?)?;
+        assert_eq!(sum, Money::fromDecimal("0.3", "USD")?);
+        assert_eq!(a.to_string(), "$19.99");
+        assert_eq!(Money::parse("1.299,99 €", "EUR")?.to_string(), "€1,299.99");
+        Ok(())
+    }
+
+    #[test]
+    fn format_price()
+    {
+        assert_eq!(formatPrice(1299, "USD"), "$12.99");
+        assert_eq!(formatPrice(129999, "USD"), "$1,299.99");
+        assert_eq!(formatPrice(5, "USD"), "$0.05");
+        assert_eq!(formatPrice(7800, "HKD"), "HK$78.00");
+        assert_eq!(formatPrice(150000, "JPY"), "¥1,500");
+        assert_eq!(formatPrice(123456789, "EUR"), "€1,234,567.89");
+        assert_eq!(formatPrice(1050, "CHF"), "CHF 10.50");
+        assert_eq!(formatPrice(-250, "USD"), "-$2.50");
+    }
+
+    #[test]
+    fn parse_duration() -> Result<(), Error>
+    {
+        assert_eq!(parseDuration("90")?, Duration::from_secs(90));
+        assert_eq!(parseDuration("90s")?, Duration::from_secs(90));
+        assert_eq!(parseDuration("30m")?, Duration::from_secs(1800));
+        assert_eq!(parseDuration("2h")?, Duration::from_secs(7200));
+        assert_eq!(parseDuration("1d")?, Duration::from_secs(86400));
+        assert!(parseDuration("").is_err());
+        assert!(parseDuration("h").is_err());
+        assert!(parseDuration("2w").is_err());
+        assert!(parseDuration("-5").is_err());
+        assert_eq!(parseDuration("0").unwrap_err().kind(), "invalid_input");
+        assert_eq!(parseDuration("0d").unwrap_err().kind(), "invalid_input");
+        assert_eq!(parseDuration("999999999999999999d").unwrap_err().kind(),
+                   "invalid_input");
+        assert_eq!(parseDuration("9223372036854775808").unwrap_err().kind(),
+                   "invalid_input");
+        assert_eq!(parseDuration("9223372036854775807")?.as_secs(),
+                   i64::MAX as u64);
+        Ok(())
+    }
 }
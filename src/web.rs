@@ -1,16 +1,27 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 use tokio;
 use warp;
 use warp::reject::Reject;
 use warp::{Filter, Rejection};
 use warp::reply::Reply;
+use warp::http::StatusCode;
 use log::error as log_error;
-use log::info;
+use log::{debug, info};
 
 use crate::error::Error as Error;
 use crate::data;
 use crate::config;
 use crate::store;
+use crate::middle;
+use crate::alert;
+use crate::exchange;
+use crate::http;
+use crate::notify;
+use crate::utils::Money;
 
 const ENTRY: &str = "api";
 
@@ -18,16 +29,86 @@ const ENTRY: &str = "api";
 struct ResError
 {
     msg: String,
+    /// Machine-readable error type, see `Error::kind()`.
+    kind: String,
 }
 
 impl From<&str> for ResError
 {
     fn from(s: &str) -> Self
     {
-        Self { msg: s.to_owned() }
+        Self { msg: s.to_owned(), kind: String::from("runtime") }
+    }
+}
+
+impl From<&Error> for ResError
+{
+    fn from(e: &Error) -> Self
+    {
+        Self { msg: e.to_string(), kind: e.kind().to_owned() }
+    }
+}
+
+/// The HTTP status to respond with on an error.
+fn errorStatus(e: &Error) -> StatusCode
+{
+    match e
+    {
+        Error::NotFound(..) => StatusCode::NOT_FOUND,
+        Error::InvalidInput(..) => StatusCode::BAD_REQUEST,
+        // The store misbehaved, not us.
+        Error::Network(..) | Error::Parse(..) | Error::StoreChanged(..) =>
+            StatusCode::BAD_GATEWAY,
+        Error::RuntimeError(..) | Error::DataError(..) | Error::Config(..) =>
+            StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
+fn errorReply(e: &Error) -> warp::reply::WithStatus<warp::reply::Json>
+{
+    warp::reply::with_status(warp::reply::json(&ResError::from(e)),
+                             errorStatus(e))
+}
+
+/// Render rejections as `ResError` JSON. Errors from the handlers get
+/// the status code from `errorStatus()`.
+async fn handleRejection(r: Rejection) ->
+    Result<warp::reply::WithStatus<warp::reply::Json>, Infallible>
+{
+    if let Some(e) = r.find::<Error>()
+    {
+        if errorStatus(e) == StatusCode::INTERNAL_SERVER_ERROR
+        {
+            log_error!("{}", e);
+        }
+        return Ok(errorReply(e));
+    }
+
+    let (status, msg) = if r.is_not_found()
+    {
+        (StatusCode::NOT_FOUND, "Not found")
+    }
+    else if r.find::<warp::reject::InvalidQuery>().is_some()
+    {
+        (StatusCode::BAD_REQUEST, "Invalid query")
+    }
+    else if r.find::<warp::filters::body::BodyDeserializeError>().is_some()
+    {
+        (StatusCode::BAD_REQUEST, "Invalid request body")
+    }
+    else if r.find::<warp::reject::MethodNotAllowed>().is_some()
+    {
+        (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+    }
+    else
+    {
+        log_error!("Unhandled rejection: {:?}", r);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal error")
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&ResError::from(msg)),
+                                status))
+}
+
 impl Reject for Error {}
 
 fn withOptionalPrefix(prefix: Option<String>) ->
@@ -44,16 +125,16 @@ fn withOptionalPrefix(prefix: Option<String>) ->
             }
             if segs.len() == 1
             {
-                warp::get().and(warp::path(s)).boxed()
+                warp::path(s).boxed()
             }
             else                // len = 2
             {
-                warp::get().and(warp::path(segs[0].to_owned()))
+                warp::path(segs[0].to_owned())
                     .and(warp::path(segs[1].to_owned()))
                     .boxed()
             }
         },
-        None => warp::get().boxed(),
+        None => warp::any().boxed(),
     }
 }
 
@@ -62,6 +143,110 @@ struct PricePoint
 {
     time: u64,
     price: f64,
+    currency: String,
+    availability: store::Availability,
+}
+
+/// An item in the item list.
+#[derive(Serialize)]
+struct ListItem
+{
+    #[serde(flatten)]
+    item: store::ItemInfo,
+    /// When the price will be queried next, in seconds since epoch.
+    next_update: i64,
+}
+
+/// An item in the summary, with its price in the home currency.
+#[derive(Serialize, Deserialize)]
+struct SummaryItem
+{
+    store: String,
+    id: String,
+    name: String,
+    price: f64,
+    currency: String,
+    /// Null if there is no exchange rate for `currency`.
+    home_price: Option<f64>,
+    home_price_str: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Summary
+{
+    /// The home currency.
+    currency: String,
+    /// Sum of the prices that could be converted. It is added up in
+    /// price * 100 like all prices, and only divided for display.
+    total: f64,
+    total_str: String,
+    items: Vec<SummaryItem>,
+    /// Currencies without an exchange rate. Items in these currencies
+    /// are not in the total.
+    missing_rates: Vec<String>,
+}
+
+impl Summary
+{
+    fn new(items: &[store::ItemInfo], rates: &exchange::Rates,
+           home_currency: &str) -> Result<Self, Error>
+    {
+        let mut total = Money::new(0, home_currency);
+        let mut missing_rates: Vec<String> = Vec::new();
+        let mut summary_items = Vec::new();
+        for item in items
+        {
+            let home_price = match rates.convert(item.price, &item.currency,
+                                                 home_currency)
+            {
+                Ok(p) =>
+                {
+                    total = total.plus(&Money::new(p, home_currency))?;
+                    Some(p)
+                },
+                Err(_) =>
+                {
+                    if !missing_rates.contains(&item.currency)
+                    {
+                        missing_rates.push(item.currency.clone());
+                    }
+                    None
+                },
+            };
+            summary_items.push(SummaryItem {
+                store: item.store.clone(),
+                id: item.id.clone(),
+                name: item.name.clone(),
+                price: (item.price as f64) / 100.0,
+                currency: item.currency.clone(),
+                home_price: home_price.map(|p| (p as f64) / 100.0),
+                home_price_str: home_price.map(
+                    |p| Money::new(p, home_currency).to_string()),
+            });
+        }
+        Ok(Self {
+            currency: home_currency.to_owned(),
+            total: (total.amount as f64) / 100.0,
+            total_str: total.to_string(),
+            items: summary_items,
+            missing_rates,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ResolveQuery
+{
+    url: String,
+}
+
+/// An item to add by the URL of its product page.
+#[derive(Deserialize)]
+struct AddRequest
+{
+    url: String,
+    #[serde(default)]
+    alert: alert::Settings,
 }
 
 #[derive(Clone)]
@@ -70,39 +255,68 @@ pub struct WebHandler
     db_file: String,
     port: u16,
     url_prefix: Option<String>,
+    registry: Arc<store::Registry>,
+    update_interval: Duration,
+    home_currency: String,
+    rates: Arc<dyn exchange::RateProvider>,
+    /// Run the price update scheduler with this config and these
+    /// notifiers, if set.
+    scheduler: Option<(config::ConfigParams, Arc<notify::Notifiers>)>,
 }
 
-/// Take the 1st Result as argument. If Result is an error, let the
-/// current function return a 500 error, otherwide run the 2nd
-/// argument on the unwrapped value of the 1st argument. The 2nd
-/// argument is an expression that do some thing with the unwrapped
-/// value. The unwrapped value is assumed to be called “ok”.
+/// Take a Result as argument. If it is an error, let the current
+/// function reject with the error, which `handleRejection()` turns
+/// into a JSON reply. Otherwise evaluate to the unwrapped value.
 macro_rules! web_error
 {
     ($stuff:expr) =>
     {
-        $stuff.map_err(|e| warp::reject::custom(e))?
+        $stuff.map_err(warp::reject::custom)?
     }
 }
 
 impl WebHandler
 {
-    pub fn new(conf: &config::ConfigParams) -> Self
+    /// A handler that queries the stores, the exchange rates and the
+    /// notifiers with `http`.
+    pub fn new(conf: &config::ConfigParams, http: Arc<http::Client>) ->
+        Result<Self, Error>
     {
-        Self {
+        let registry = store::Registry::fromConfig(conf, http.clone())?;
+        let rates: Arc<dyn exchange::RateProvider> =
+            Arc::from(exchange::fromConfig(conf, http.clone()));
+        debug!("Using exchange rates from {}", rates.name());
+        Ok(Self {
             db_file: conf.db_file.clone(),
             port: conf.port,
             url_prefix: conf.url_prefix.clone(),
-        }
+            registry: Arc::new(registry),
+            update_interval: Duration::from_secs(conf.update_interval_sec),
+            home_currency: conf.home_currency.clone(),
+            rates,
+            scheduler: if conf.update_in_server
+            {
+                Some((conf.clone(),
+                      Arc::new(notify::Notifiers::fromConfig(conf, http)?)))
+            }
+            else
+            {
+                None
+            },
+        })
     }
 
     async fn list(self) -> Result<Box<dyn Reply>, Rejection>
     {
+        let update_interval = self.update_interval;
         let mut d = data::DataManager::new(data::SqliteFilename::File(
             std::path::PathBuf::from(self.db_file)));
         web_error!(d.connect());
-        let items: Vec<store::ItemInfo> = web_error!(d.getItems())
-            .iter().map(|p| p.clone()).collect();
+        let items: Vec<ListItem> = web_error!(d.getItems())
+            .into_iter().map(|item| ListItem {
+                next_update: item.nextUpdate(update_interval).timestamp(),
+                item,
+            }).collect();
         Ok(Box::new(warp::reply::json(&items)))
     }
 
@@ -112,38 +326,422 @@ impl WebHandler
         let mut d = data::DataManager::new(data::SqliteFilename::File(
             std::path::PathBuf::from(self.db_file)));
         web_error!(d.connect());
-        let item = data::ItemKey{ store: store, id: id };
-        let points: Vec<PricePoint> = d.priceHistory(item)?.iter().map(
+        let item = data::ItemKey{ store, id };
+        let points: Vec<PricePoint> = web_error!(d.priceHistory(item)).iter().map(
             |p| PricePoint{
                 time: p.time.timestamp() as u64,
                 price: (p.price as f64) / 100.0,
+                currency: p.currency.clone(),
+                availability: p.availability,
             }).collect();
 
         Ok(Box::new(warp::reply::json(&points)))
     }
 
-    pub fn start(self)
+    /// The wishlist total and the price of each item in the home
+    /// currency.
+    async fn summary(self) -> Result<Box<dyn Reply>, Rejection>
+    {
+        let mut d = data::DataManager::new(data::SqliteFilename::File(
+            std::path::PathBuf::from(self.db_file)));
+        web_error!(d.connect());
+        let items = web_error!(d.getItems());
+        let rates = web_error!(self.rates.rates().await);
+        let summary = web_error!(Summary::new(&items, &rates,
+                                              &self.home_currency));
+        Ok(Box::new(warp::reply::json(&summary)))
+    }
+
+    /// Replace the alert settings of an item.
+    async fn setAlert(self, store: String, id: String,
+                      settings: alert::Settings) ->
+        Result<Box<dyn Reply>, Rejection>
+    {
+        let mut d = data::DataManager::new(data::SqliteFilename::File(
+            std::path::PathBuf::from(self.db_file)));
+        web_error!(d.connect());
+        web_error!(d.updateItem(data::ItemKey{ store, id },
+                                &data::ItemUpdate::fromAlert(&settings)));
+        Ok(Box::new(warp::reply::json(&settings)))
+    }
+
+    /// Find the store and ID of an item from the URL of its product
+    /// page.
+    async fn resolve(self, query: ResolveQuery) ->
+        Result<Box<dyn Reply>, Rejection>
+    {
+        match self.registry.parseURL(&query.url)
+        {
+            Some(key) => Ok(Box::new(warp::reply::json(&key))),
+            None => Err(warp::reject::custom(
+                error!(NotFound, "Unrecognized URL: {}", query.url))),
+        }
+    }
+
+    /// Add an item by the URL of its product page, and reply with it
+    /// like in the item list.
+    async fn add(self, req: AddRequest) -> Result<Box<dyn Reply>, Rejection>
+    {
+        let item = web_error!(middle::fetchAndAddItemByURL(
+            &req.url, &req.alert, None, &self.db_file, &self.registry).await);
+        Ok(Box::new(warp::reply::with_status(
+            warp::reply::json(&ListItem {
+                next_update: item.nextUpdate(self.update_interval).timestamp(),
+                item,
+            }),
+            StatusCode::CREATED)))
+    }
+
+    /// All the routes of the service, under the URL prefix.
+    fn routes(self) -> warp::filters::BoxedFilter<(Box<dyn Reply>,)>
     {
-        let port = self.port;
         let url_prefix: Option<String> = self.url_prefix.clone();
         let handler = self.clone();
+        // Match the path before the method, so that an unknown path is
+        // a 404 instead of a 405.
         let route_list = warp::path(ENTRY).and(warp::path("list"))
-            .and(warp::path::end())
+            .and(warp::path::end()).and(warp::get())
             .and_then(move || { handler.clone().list() });
+        let handler = self.clone();
         let route_history = warp::path(ENTRY).and(warp::path("price_history"))
             .and(warp::path::param()).and(warp::path::param())
+            .and(warp::path::end()).and(warp::get())
             .and_then(move |store: String, id: String| {
-                self.clone().priceHistory(store, id)
+                handler.clone().priceHistory(store, id)
+            });
+        let handler = self.clone();
+        let route_summary = warp::path(ENTRY).and(warp::path("summary"))
+            .and(warp::path::end()).and(warp::get())
+            .and_then(move || { handler.clone().summary() });
+        let handler = self.clone();
+        let route_alert = warp::path(ENTRY).and(warp::path("alert"))
+            .and(warp::path::param()).and(warp::path::param())
+            .and(warp::path::end()).and(warp::put()).and(warp::body::json())
+            .and_then(move |store: String, id: String, settings| {
+                handler.clone().setAlert(store, id, settings)
+            });
+        let handler = self.clone();
+        let route_resolve = warp::path(ENTRY).and(warp::path("resolve"))
+            .and(warp::path::end()).and(warp::get())
+            .and(warp::query::<ResolveQuery>())
+            .and_then(move |query: ResolveQuery| {
+                handler.clone().resolve(query)
             });
+        let route_add = warp::path(ENTRY).and(warp::path("add"))
+            .and(warp::path::end()).and(warp::post()).and(warp::body::json())
+            .and_then(move |req: AddRequest| { self.clone().add(req) });
+
+        let route_fe = warp::get().and(warp::fs::dir("frontend"))
+            .map(|f| Box::new(f) as Box<dyn Reply>);
 
-        let route_fe = warp::any().and(warp::fs::dir("frontend"));
+        withOptionalPrefix(url_prefix)
+            .and(route_list.or(route_history).unify().or(route_summary)
+                 .unify().or(route_alert)
+                 .unify().or(route_resolve).unify().or(route_add).unify()
+                 .or(route_fe).unify())
+            .recover(handleRejection)
+            .map(|r| Box::new(r) as Box<dyn Reply>)
+            .boxed()
+    }
 
+    pub fn start(self)
+    {
+        let port = self.port;
+        let url_prefix: Option<String> = self.url_prefix.clone();
+        let scheduler = self.scheduler.clone();
+        let registry = self.registry.clone();
+        let routes = self.routes();
         let rt = tokio::runtime::Runtime::new().unwrap();
+        if let Some((conf, notifiers)) = scheduler
+        {
+            rt.spawn(middle::runScheduler(conf, registry, notifiers));
+        }
         info!("Running service at http://127.0.0.1:{}/{}", port,
               url_prefix.as_ref().unwrap_or(&String::new()));
         rt.block_on(
-            warp::serve(withOptionalPrefix(url_prefix)
-                        .and(route_list.or(route_history).or(route_fe)))
-                .try_bind(([127, 0, 0, 1], port)));
+            warp::serve(routes).try_bind(([127, 0, 0, 1], port)));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    type AnyError = Box<dyn std::error::Error>;
+
+    /// A handler with a database in `dir` that has one item.
+    fn newHandler(dir: &tempfile::TempDir) -> Result<WebHandler, AnyError>
+    {
+        let mut conf = config::ConfigParams::default();
+        conf.db_file = dir.path().join("test.db").to_string_lossy().into_owned();
+        let mut d = data::DataManager::newWithFilename(&conf.db_file);
+        d.connect()?;
+        let mut item = store::ItemInfo::new("ps4-us", "aaa");
+        item.name = String::from("Some game");
+        item.price = 1999;
+        item.price_str = String::from("$19.99");
+        item.currency = String::from("USD");
+        item.availability = store::Availability::InStock;
+        d.addItem(&item)?;
+        d.addPrice(&item)?;
+        Ok(WebHandler::new(&conf, Arc::new(http::Client::new(&conf.http)?))?)
+    }
+
+    fn request(handler: &WebHandler, path: &str) ->
+        warp::http::Response<warp::hyper::body::Bytes>
+    {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(warp::test::request().path(path)
+                    .reply(&handler.clone().routes()))
+    }
+
+    fn errorBody(res: &warp::http::Response<warp::hyper::body::Bytes>) ->
+        ResError
+    {
+        serde_json::from_slice(res.body()).unwrap()
+    }
+
+    #[test]
+    fn list() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let handler = newHandler(&dir)?;
+        let res = request(&handler, "/api/list");
+        assert_eq!(res.status(), StatusCode::OK);
+        let items: serde_json::Value = serde_json::from_slice(res.body())?;
+        assert_eq!(items[0]["name"], "Some game");
+        assert_eq!(items[0]["availability"], "in_stock");
+        assert!(items[0]["next_update"].is_i64());
+        Ok(())
+    }
+
+    #[test]
+    fn price_history() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let handler = newHandler(&dir)?;
+        let res = request(&handler, "/api/price_history/ps4-us/aaa");
+        assert_eq!(res.status(), StatusCode::OK);
+        let points: Vec<PricePoint> = serde_json::from_slice(res.body())?;
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].price, 19.99);
+        assert_eq!(points[0].currency, "USD");
+        assert_eq!(points[0].availability, store::Availability::InStock);
+
+        let res = request(&handler, "/api/price_history/ps4-us/bbb");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(errorBody(&res).kind, "not_found");
+        Ok(())
+    }
+
+    #[test]
+    fn summary() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let rates_file = dir.path().join("rates.toml");
+        std::fs::write(&rates_file, "base = \"USD\"\n[rates]\nHKD = 7.8\n")?;
+        let mut handler = newHandler(&dir)?;
+        let mut d = data::DataManager::newWithFilename(&handler.db_file);
+        d.connect()?;
+        for (id, price, currency) in [("bbb", 7800, "HKD"), ("ccc", 1000, "EUR")]
+        {
+            let mut item = store::ItemInfo::new("ps4-hk", id);
+            item.price = price;
+            item.currency = String::from(currency);
+            d.addItem(&item)?;
+            d.addPrice(&item)?;
+        }
+
+        let mut conf = config::ConfigParams::default();
+        conf.exchange_rates = Some(config::ExchangeRateParams::File {
+            path: rates_file.to_string_lossy().into_owned() });
+        handler.rates = Arc::from(exchange::fromConfig(
+            &conf, handler.registry.http()));
+        let res = request(&handler, "/api/summary");
+        assert_eq!(res.status(), StatusCode::OK);
+        let summary: Summary = serde_json::from_slice(res.body())?;
+        assert_eq!(summary.currency, "USD");
+        assert_eq!(summary.total, 29.99);
+        assert_eq!(summary.total_str, "$29.99");
+        assert_eq!(summary.missing_rates, vec![String::from("EUR")]);
+        let hkd = summary.items.iter().find(|i| i.id == "bbb").unwrap();
+        assert_eq!(hkd.price, 78.0);
+        assert_eq!(hkd.home_price, Some(10.0));
+        assert_eq!(hkd.home_price_str.as_deref(), Some("$10.00"));
+        let eur = summary.items.iter().find(|i| i.id == "ccc").unwrap();
+        assert_eq!(eur.home_price, None);
+
+        std::fs::remove_file(&rates_file)?;
+        let res = request(&handler, "/api/summary");
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(errorBody(&res).kind, "config");
+        Ok(())
+    }
+
+    #[test]
+    fn summary_total_is_exact() -> Result<(), Error>
+    {
+        // 0.1 + 0.2 is not 0.3 in floating point.
+        let items: Vec<store::ItemInfo> = [10, 20].iter().map(|p| {
+            let mut item = store::ItemInfo::new("ps4-us", &p.to_string());
+            item.price = *p;
+            item.currency = String::from("USD");
+            item
+        }).collect();
+        let rates = exchange::Rates::empty("USD");
+        let summary = Summary::new(&items, &rates, "USD")?;
+        assert_eq!(summary.total, 0.3);
+        assert_eq!(summary.total_str, "$0.30");
+
+        // A total that does not fit is an error, not a wrong number.
+        let mut items = items;
+        items[0].price = i64::MAX;
+        assert_eq!(Summary::new(&items, &rates, "USD").err().map(|e| e.kind()),
+                   Some("runtime"));
+        Ok(())
+    }
+
+    #[test]
+    fn resolve() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let handler = newHandler(&dir)?;
+        let res = request(&handler, "/api/resolve?url=https%3A%2F%2Fwww.nintendo.com%2Fgames%2Fdetail%2Fhollow-knight-switch%2F");
+        assert_eq!(res.status(), StatusCode::OK);
+        let key: data::ItemKey = serde_json::from_slice(res.body())?;
+        assert_eq!(key.store, "switch-us");
+
+        let res = request(&handler, "/api/resolve?url=https%3A%2F%2Fexample.com%2F");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(errorBody(&res).kind, "not_found");
+
+        let res = request(&handler, "/api/resolve");
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    /// Sells every item at $25, with pages at “dummy.example/<id>”.
+    struct Dummy;
+
+    #[async_trait::async_trait]
+    impl store::StoreBackend for Dummy
+    {
+        fn name(&self) -> &str { "dummy" }
+
+        fn regions(&self) -> Vec<String> { Vec::new() }
+
+        async fn get(&self, _http: &http::StoreClient, _region: Option<&str>,
+                     id: &str) -> Result<store::ItemInfo, Error>
+        {
+            let mut item = store::ItemInfo::new("dummy", id);
+            item.name = format!("Game {}", id);
+            item.setPrice(&Money::new(2500, "USD"));
+            item.availability = store::Availability::InStock;
+            Ok(item)
+        }
+
+        fn parseURL(&self, url: &str) -> Option<data::ItemKey>
+        {
+            url.strip_prefix("https://dummy.example/").map(
+                |id| data::ItemKey { store: String::from("dummy"),
+                                     id: id.to_owned() })
+        }
+    }
+
+    #[test]
+    fn add() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let mut handler = newHandler(&dir)?;
+        let mut registry = store::Registry::empty(handler.registry.http());
+        registry.register(Arc::new(Dummy))?;
+        handler.registry = Arc::new(registry);
+        let post = |body: &str| {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(warp::test::request().method("POST").path("/api/add")
+                        .body(body)
+                        .reply(&handler.clone().routes()))
+        };
+        let res = post(r#"{"url": "https://dummy.example/bbb",
+                           "alert": {"alert_price": 1500}}"#);
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let item: serde_json::Value = serde_json::from_slice(res.body())?;
+        assert_eq!(item["store"], "dummy");
+        assert_eq!(item["id"], "bbb");
+        assert_eq!(item["price_str"], "$25.00");
+        assert!(item["next_update"].is_i64());
+        let res = request(&handler, "/api/list");
+        let items: serde_json::Value = serde_json::from_slice(res.body())?;
+        let added = items.as_array().unwrap().iter()
+            .find(|i| i["id"] == "bbb").unwrap();
+        assert_eq!(added["name"], "Game bbb");
+        assert_eq!(added["alert_price"], 1500);
+
+        let res = post(r#"{"url": "https://example.com/"}"#);
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(errorBody(&res).kind, "not_found");
+        let res = post(r#"{"url": "https://dummy.example/ccc",
+                           "alert": {"alert_drop_percent": 120}}"#);
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = post("not json");
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = request(&handler, "/api/list");
+        let items: serde_json::Value = serde_json::from_slice(res.body())?;
+        assert_eq!(items.as_array().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn set_alert() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let handler = newHandler(&dir)?;
+        let put = |path: &str, body: &str| {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(warp::test::request().method("PUT").path(path)
+                        .body(body)
+                        .reply(&handler.clone().routes()))
+        };
+        let res = put("/api/alert/ps4-us/aaa",
+                      r#"{"alert_price": 1500, "alert_all_time_low": true}"#);
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = request(&handler, "/api/list");
+        let items: serde_json::Value = serde_json::from_slice(res.body())?;
+        assert_eq!(items[0]["alert_price"], 1500);
+        assert_eq!(items[0]["alert_drop_percent"], serde_json::Value::Null);
+        assert_eq!(items[0]["alert_all_time_low"], true);
+
+        let res = put("/api/alert/ps4-us/aaa", r#"{"alert_drop_percent": 120}"#);
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(errorBody(&res).kind, "invalid_input");
+        let res = put("/api/alert/ps4-us/aaa", "not json");
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = put("/api/alert/ps4-us/bbb", "{}");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[test]
+    fn database_error() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let mut handler = newHandler(&dir)?;
+        // A directory cannot be opened as a database.
+        handler.db_file = dir.path().to_string_lossy().into_owned();
+        let res = request(&handler, "/api/list");
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(errorBody(&res).kind, "data");
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_route() -> Result<(), AnyError>
+    {
+        let dir = tempfile::tempdir()?;
+        let handler = newHandler(&dir)?;
+        let res = request(&handler, "/api/nothing");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        Ok(())
     }
 }